@@ -1,125 +1,345 @@
-use regex::{Captures, Match, Regex};
+use std::{borrow::Cow, io, ops::Range};
+
+use regex::bytes::{Captures, Regex};
+
+use crate::source::{Line, Origin};
+
+/// Separator used between the values of a group when all matches on a line
+/// are written to a single record, see `RecordLayout::Joined`
+pub const JOINED_SEPARATOR: &str = ";";
 
 #[derive(Debug, PartialEq)]
 pub enum MatchType {
     Normal(String),
-    Group(String),
+    /// Text matched by the whole pattern, but outside of any capture group
+    Match(String),
+    /// Text inside one or more capture groups. `layers` holds the indices of the
+    /// groups it belongs to, ordered from the outermost to the innermost group, and
+    /// `name` is the name of the innermost group, if it has one
+    Group {
+        text: String,
+        layers: Vec<usize>,
+        name: Option<String>,
+    },
 }
 
+/// A capture group and the groups nested inside it. `start` and `end` are byte
+/// offsets into the line, `end` being exclusive
 #[derive(Debug, PartialEq)]
-pub struct MatchItem<'a> {
-    pub text: &'a str,
-    pub mtype: MatchType,
+pub struct GroupSpan {
+    pub index: usize,
+    pub start: usize,
+    pub end: usize,
+    pub children: Vec<GroupSpan>,
+}
+
+/// How lines with several matches are written as records
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordLayout {
+    /// One record per match
+    Rows,
+    /// One record per line, with the values of each group joined by `JOINED_SEPARATOR`
+    Joined,
 }
 
+impl RecordLayout {
+    pub fn from_arg(arg: &str) -> Option<RecordLayout> {
+        match arg {
+            "rows" => Some(RecordLayout::Rows),
+            "joined" => Some(RecordLayout::Joined),
+            _ => None,
+        }
+    }
+}
+
+/// Matches of a pattern on a line. The pattern runs over the bytes of the line, and
+/// the text it matched is shown with bytes that are not valid UTF-8 as `�`
 #[derive(Debug)]
 pub struct MatchSet<'a> {
-    pub full_text: &'a str,
+    pub full_text: &'a [u8],
     pub re: &'a Regex,
-    // pub items: Vec<MatchType>,
+    pub origin: Option<&'a Origin>,
 }
 
 impl<'a> MatchSet<'a> {
+    #[cfg(test)]
     pub fn from(full_text: &'a str, re: &'a Regex) -> Self {
-        MatchSet { full_text, re }
+        MatchSet {
+            full_text: full_text.as_bytes(),
+            re,
+            origin: None,
+        }
     }
 
-    pub fn raw_line(&self) -> String {
-        self.full_text.to_string()
+    pub fn from_line(line: &'a Line, re: &'a Regex) -> Self {
+        MatchSet {
+            full_text: &line.bytes,
+            re,
+            origin: Some(&line.origin),
+        }
     }
 
-    pub fn to_csv_row(&self) -> String {
-        self.to_strings().join(",")
+    pub fn raw_line(&self) -> String {
+        String::from_utf8_lossy(self.full_text).into_owned()
     }
 
+    /// Captured groups of every match on the line, one entry per group.
+    /// When the pattern matches several times, the values of each group are joined
+    /// by `JOINED_SEPARATOR`
     pub fn to_strings(&self) -> Vec<String> {
-        let mut items = Vec::new();
-        let captures = self.re.captures(self.full_text).unwrap();
-
-        match captures.len() {
-            0..=1 => items.push(MatchType::Normal(self.full_text.to_string())),
-            _ => {
-                let mut previous_end = 0;
-                for i in 1..captures.len() {
-                    if let Some(mat) = captures.get(i) {
-                        if mat.start() != previous_end {
-                            items.push(MatchType::Normal(
-                                self.full_text[previous_end..mat.start()].to_string(),
-                            ));
-                        }
-                        items.push(MatchType::Group(
-                            self.full_text[mat.start()..mat.end()].to_string(),
-                        ));
-                        previous_end = mat.end();
-                    }
-                }
-                if previous_end != self.full_text.len() {
-                    items.push(MatchType::Normal(
-                        self.full_text[previous_end..].to_string(),
-                    ))
-                }
-            }
-        }
-        let res: Vec<String> = items
-            .iter()
-            .filter(|mt| match mt {
-                MatchType::Group(_) => true,
-                _ => false,
+        let rows = self.to_rows();
+        (0..exported_groups(self.re.captures_len()).len())
+            .map(|i| {
+                rows.iter()
+                    .map(|row| row[i].as_str())
+                    .collect::<Vec<&str>>()
+                    .join(JOINED_SEPARATOR)
+            })
+            .collect()
+    }
+
+    /// Captured groups split into one row per match. Groups that did not participate
+    /// in a match are returned as empty strings so that all rows have the same width
+    pub fn to_rows(&self) -> Vec<Vec<String>> {
+        self.to_captures()
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|group| group.map(Cow::into_owned).unwrap_or_default())
+                    .collect()
             })
-            .map(|mt| match mt {
-                MatchType::Group(s) => s.to_string(),
-                _ => "".to_string(),
+            .collect()
+    }
+
+    /// Captured groups split into one row per match, with `None` for groups that did
+    /// not participate in a match
+    pub fn to_captures(&self) -> Vec<Vec<Option<Cow<'a, str>>>> {
+        self.re
+            .captures_iter(self.full_text)
+            .map(|captures| {
+                exported_groups(captures.len())
+                    .map(|i| {
+                        captures
+                            .get(i)
+                            .map(|m| String::from_utf8_lossy(m.as_bytes()))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn to_records(&self, layout: RecordLayout) -> Vec<Vec<String>> {
+        match layout {
+            RecordLayout::Rows => self.to_rows(),
+            RecordLayout::Joined => vec![self.to_strings()],
+        }
+    }
+
+    /// Every match on the line as a span of group 0, with capture groups as children
+    /// of the group enclosing them
+    pub fn to_spantree(&self) -> Vec<GroupSpan> {
+        self.re
+            .captures_iter(self.full_text)
+            .filter_map(|captures| {
+                let mat = captures.get(0)?;
+                if mat.start() == mat.end() {
+                    return None;
+                }
+                Some(GroupSpan {
+                    index: 0,
+                    start: mat.start(),
+                    end: mat.end(),
+                    children: span_tree(&captures),
+                })
             })
-            .collect();
-        res
+            .collect()
     }
 
+    /// Line split into consecutive segments, each tagged with the groups it is part of
     pub fn to_matchtypes(&self) -> Vec<MatchType> {
+        let mut segments = Segments::new(self.full_text, self.re);
+        segments.walk(&self.to_spantree(), 0, self.full_text.len());
+        if segments.items.is_empty() {
+            segments.items.push(MatchType::Normal(self.raw_line()));
+        }
+        segments.items
+    }
+
+    /// Line with every match replaced by `template`, where `$1` or `${name}` insert the
+    /// text captured by a group, as in `Regex::replace_all`
+    pub fn replaced(&self, template: &str) -> String {
+        let replaced = self.re.replace_all(self.full_text, template.as_bytes());
+        String::from_utf8_lossy(&replaced).into_owned()
+    }
+
+    /// Replaced line split into the text kept from the line, as `Normal`, and the text
+    /// each match was replaced with, as `Match`
+    pub fn to_replaced_matchtypes(&self, template: &str) -> Vec<MatchType> {
         let mut items = Vec::new();
-        let captures = self.re.captures(self.full_text).unwrap();
-
-        match captures.len() {
-            0..=1 => items.push(MatchType::Normal(self.full_text.to_string())),
-            _ => {
-                let mut previous_end = 0;
-                for i in 1..captures.len() {
-                    if let Some(mat) = captures.get(i) {
-                        if mat.start() != previous_end {
-                            items.push(MatchType::Normal(
-                                self.full_text[previous_end..mat.start()].to_string(),
-                            ));
-                        }
-                        items.push(MatchType::Group(
-                            self.full_text[mat.start()..mat.end()].to_string(),
-                        ));
-                        previous_end = mat.end();
-                    }
-                }
-                if previous_end != self.full_text.len() {
-                    items.push(MatchType::Normal(
-                        self.full_text[previous_end..].to_string(),
-                    ))
-                }
+        let mut kept = 0;
+        for captures in self.re.captures_iter(self.full_text) {
+            let mat = captures.get(0).unwrap();
+            if mat.start() > kept {
+                items.push(MatchType::Normal(lossy(&self.full_text[kept..mat.start()])));
+            }
+            let mut replacement = Vec::new();
+            captures.expand(template.as_bytes(), &mut replacement);
+            if !replacement.is_empty() {
+                items.push(MatchType::Match(lossy(&replacement)));
             }
+            kept = mat.end();
+        }
+        if kept < self.full_text.len() {
+            items.push(MatchType::Normal(lossy(&self.full_text[kept..])));
         }
         items
     }
+}
 
-    pub fn to_tsv_row(&self) -> String {
-        self.to_strings().join("\t")
-    }
+/// Text of `bytes`, with bytes that are not valid UTF-8 as `�`
+fn lossy(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
 }
 
-pub fn filter_matches<'a>(contents: &'a [String], re: &Regex) -> Vec<&'a str> {
-    contents
+/// Builds the tree of non-empty groups in a single match. Groups are syntactically
+/// nested in the pattern, so sorting by start, and then by longest span first, puts
+/// every group right after the group enclosing it
+fn span_tree(captures: &Captures) -> Vec<GroupSpan> {
+    let mut spans: Vec<(usize, usize, usize)> = captures
         .iter()
-        .map(String::as_str)
-        .filter(|s| re.is_match(s))
+        .enumerate()
+        .skip(1)
+        .filter_map(|(index, mat)| mat.map(|m| (index, m.start(), m.end())))
+        .filter(|(_, start, end)| start != end)
+        .collect();
+    spans.sort_by_key(|&(index, start, end)| (start, std::cmp::Reverse(end), index));
+    build_spans(&mut spans.into_iter().peekable(), usize::MAX)
+}
+
+fn build_spans<I>(spans: &mut std::iter::Peekable<I>, parent_end: usize) -> Vec<GroupSpan>
+where
+    I: Iterator<Item = (usize, usize, usize)>,
+{
+    let mut nodes = Vec::new();
+    while let Some(&(index, start, end)) = spans.peek() {
+        if start >= parent_end || end > parent_end {
+            break;
+        }
+        spans.next();
+        let children = build_spans(spans, end);
+        nodes.push(GroupSpan {
+            index,
+            start,
+            end,
+            children,
+        });
+    }
+    nodes
+}
+
+/// Splits a line into `MatchType` segments by walking its span tree depth first
+struct Segments<'a> {
+    text: &'a [u8],
+    names: Vec<Option<&'a str>>,
+    /// Indices of the groups enclosing the current position, starting with group 0
+    /// for text inside a match
+    layers: Vec<usize>,
+    items: Vec<MatchType>,
+}
+
+impl<'a> Segments<'a> {
+    fn new(text: &'a [u8], re: &'a Regex) -> Self {
+        Segments {
+            text,
+            names: re.capture_names().collect(),
+            layers: Vec::new(),
+            items: Vec::new(),
+        }
+    }
+
+    /// Pushes the text between `from` and `to` as segments, descending into `spans`
+    fn walk(&mut self, spans: &[GroupSpan], from: usize, to: usize) {
+        let mut cursor = from;
+        for span in spans {
+            // a repeated group can keep the capture of an earlier iteration, which may
+            // lie before text that has already been pushed
+            let start = span.start.max(cursor);
+            let end = span.end.min(to);
+            if start >= end {
+                continue;
+            }
+            self.push(cursor, start);
+            self.layers.push(span.index);
+            self.walk(&span.children, start, end);
+            self.layers.pop();
+            cursor = end;
+        }
+        self.push(cursor, to);
+    }
+
+    fn push(&mut self, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        let text = lossy(&self.text[start..end]);
+        let item = match self.layers.split_first() {
+            None => MatchType::Normal(text),
+            Some((_, [])) => MatchType::Match(text),
+            Some((_, groups)) => MatchType::Group {
+                text,
+                name: groups.last().and_then(|&i| self.names[i]).map(String::from),
+                layers: groups.to_vec(),
+            },
+        };
+        self.items.push(item);
+    }
+}
+
+/// Indices of the groups that are exported: the capture groups, or the whole match
+/// when the pattern has none, so that every match still has a value
+fn exported_groups(captures_len: usize) -> Range<usize> {
+    match captures_len {
+        0..=1 => 0..1,
+        len => 1..len,
+    }
+}
+
+/// Column names for the exported groups of `re`. Unnamed groups are called
+/// `group1`, `group2`... after their index, and the whole match is called `match`
+pub fn group_names(re: &Regex) -> Vec<String> {
+    let names: Vec<Option<&str>> = re.capture_names().collect();
+    exported_groups(names.len())
+        .map(|i| match names[i] {
+            Some(name) => name.to_string(),
+            None if i == 0 => "match".to_string(),
+            None => format!("group{}", i),
+        })
         .collect()
 }
 
-pub fn into_matchsets<'a>(text_lines: &[&'a str], re: &'a Regex) -> Vec<MatchSet<'a>> {
-    let result: Vec<MatchSet> = text_lines.iter().map(|s| MatchSet::from(&s, &re)).collect();
+/// Lines from `lines` that match `re`, or with `invert` the lines that don't, paired
+/// with their position in `lines`. Read errors are passed on
+pub fn filter_matches<'r, I>(
+    lines: I,
+    re: &'r Regex,
+    invert: bool,
+) -> impl Iterator<Item = io::Result<(usize, Line)>> + 'r
+where
+    I: Iterator<Item = io::Result<Line>> + 'r,
+{
+    lines.enumerate().filter_map(move |(i, line)| match line {
+        Ok(line) if re.is_match(&line.bytes) != invert => Some(Ok((i, line))),
+        Ok(_) => None,
+        Err(err) => Some(Err(err)),
+    })
+}
+
+#[cfg(test)]
+pub fn into_matchsets<'a>(lines: &'a [Line], re: &'a Regex) -> Vec<MatchSet<'a>> {
+    let result: Vec<MatchSet> = lines
+        .iter()
+        .map(|line| MatchSet::from_line(line, re))
+        .collect();
     result
 }
 
@@ -129,6 +349,20 @@ mod tests {
     use super::*;
 
     macro_rules! matchtype {
+        (Group $string:expr => $name:expr, $($layer:expr),+) => {
+            MatchType::Group {
+                text: $string.to_string(),
+                layers: vec![$($layer),+],
+                name: Some($name.to_string()),
+            }
+        };
+        (Group $string:expr, $($layer:expr),+) => {
+            MatchType::Group {
+                text: $string.to_string(),
+                layers: vec![$($layer),+],
+                name: None,
+            }
+        };
         ($style:ident $string:expr) => {
             MatchType::$style($string.to_string())
         };
@@ -158,24 +392,70 @@ mod tests {
 
     test_matchset_from! {
         into_match_set_basetest : (r".+(hello).+(world)", "lala hello bleble world", vec![
-            matchtype!(Normal "lala "),
-            matchtype!(Group "hello"),
-            matchtype!(Normal " bleble "),
-            matchtype!(Group "world"),
+            matchtype!(Match "lala "),
+            matchtype!(Group "hello", 1),
+            matchtype!(Match " bleble "),
+            matchtype!(Group "world", 2),
+        ]),
+        givenNoCaptureGroups_thenFullTextAsSingleMatch : (r".*", "lala hello ", vec![matchtype!(Match "lala hello ")]),
+        givenNoCaptureGroups_thenSurroundingTextIsNormal : (r"\d{4}-\d{2}", "on 2021-03 and 2022-04.", vec![
+            matchtype!(Normal "on "),
+            matchtype!(Match "2021-03"),
+            matchtype!(Normal " and "),
+            matchtype!(Match "2022-04"),
+            matchtype!(Normal "."),
         ]),
-        givenNoCaptureGroups_thenFullTextAsSingleElement : (r".*", "lala hello ", vec![matchtype!(Normal "lala hello ")]),
         givenEmptyPattern_thenReturnFullTextAsSingleElement : (r"", "lala ", vec![matchtype!(Normal "lala ")]),
         givenPartialMatch_thenReturnRemainingSubstringsAsNormal : (r".*(lala)", "1337 lala hey ho!", vec![
-            matchtype!(Normal "1337 "),
-            matchtype!(Group "lala"),
+            matchtype!(Match "1337 "),
+            matchtype!(Group "lala", 1),
             matchtype!(Normal " hey ho!"),
         ]),
-        givenNonCapturingGroup_thenReturnNormal : (r"(?:lala )(bleble)", "lala bleble", vec![
-            matchtype!(Normal "lala "),
-            matchtype!(Group "bleble"),
+        givenNonCapturingGroup_thenReturnMatch : (r"(?:lala )(bleble)", "lala bleble", vec![
+            matchtype!(Match "lala "),
+            matchtype!(Group "bleble", 1),
         ]),
         given0or1MatchReturnsNone_thenDoNotReturnIt : (r"(lala)?(bleble)", "bleble", vec![
-            matchtype!(Group "bleble"),
+            matchtype!(Group "bleble", 2),
+        ]),
+        givenMultipleMatches_thenReturnGroupsOfEveryMatch : (r"(\d+)ms", "took 12ms, then 345ms", vec![
+            matchtype!(Normal "took "),
+            matchtype!(Group "12", 1),
+            matchtype!(Match "ms"),
+            matchtype!(Normal ", then "),
+            matchtype!(Group "345", 1),
+            matchtype!(Match "ms"),
+        ]),
+        givenMultipleMatchesWithMultipleGroups_thenReturnAllGroupsInOrder : (r"(\w)=(\d)", "a=1 b=2", vec![
+            matchtype!(Group "a", 1),
+            matchtype!(Match "="),
+            matchtype!(Group "1", 2),
+            matchtype!(Normal " "),
+            matchtype!(Group "b", 1),
+            matchtype!(Match "="),
+            matchtype!(Group "2", 2),
+        ]),
+        givenNestedGroups_thenTagInnerTextWithAllEnclosingGroups : (r"((\w+)@(\w+))", "mail alice@host now", vec![
+            matchtype!(Normal "mail "),
+            matchtype!(Group "alice", 1, 2),
+            matchtype!(Group "@", 1),
+            matchtype!(Group "host", 1, 3),
+            matchtype!(Normal " now"),
+        ]),
+        givenDeeplyNestedGroups_thenKeepTextInOrder : (r"(a(b(c))d)", "abcd", vec![
+            matchtype!(Group "a", 1),
+            matchtype!(Group "b", 1, 2),
+            matchtype!(Group "c", 1, 2, 3),
+            matchtype!(Group "d", 1),
+        ]),
+        givenGroupsWithSameSpan_thenOuterGroupComesFirst : (r"((ab))c", "abc", vec![
+            matchtype!(Group "ab", 1, 2),
+            matchtype!(Match "c"),
+        ]),
+        givenRepeatedGroupWithStaleInnerCapture_thenDoNotDuplicateText : (r"(a(b)?)+", "aba", vec![
+            matchtype!(Match "a"),
+            matchtype!(Group "b", 2),
+            matchtype!(Group "a", 1),
         ]),
     }
 
@@ -186,15 +466,147 @@ mod tests {
                 fn $test_name() {
                     // Given
                     let (expected, full_text, re) = $values;
-                    let match_set = MatchSet{full_text, re: &Regex::new(re).unwrap()};
-                    assert_eq!(expected, &match_set.$func_name())
+                    let match_set = MatchSet{full_text: full_text.as_bytes(), re: &Regex::new(re).unwrap(), origin: None};
+                    assert_eq!(expected, match_set.$func_name())
                 }
             )*
         }
     }
 
     test_print_options! {
-        to_csv_row : return_comma_separated_row :  ("remain,remain also", "drop remain remain also", r"\w+ (\w+) (\w+ \w+)"),
-        to_tsv_row : return_tab_separated_row : ("remain\tremain also", "drop remain remain also", r"\w+ (\w+) (\w+ \w+)"),
+        to_strings : return_one_string_per_group :  (vec!["remain", "remain also"], "drop remain remain also", r"\w+ (\w+) (\w+ \w+)"),
+        to_strings : given_multiple_matches_then_join_values_per_group : (vec!["a;b", "1;2"], "a=1 b=2", r"(\w)=(\d)"),
+    }
+
+    macro_rules! test_replace {
+        ($($func_name:ident: $values:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    // Given
+                    let (re, template, full_text, expected, items) = $values;
+                    let re = Regex::new(re).unwrap();
+                    let match_set = MatchSet::from(full_text, &re);
+                    // When
+                    let actual = match_set.replaced(template);
+                    let actual_items: Vec<MatchType> = match_set.to_replaced_matchtypes(template);
+                    // Then
+                    assert_eq!(expected, actual);
+                    assert_eq!(items, actual_items);
+                }
+            )*
+        }
+    }
+
+    test_replace! {
+        replace_every_match_with_groups : (r"(\w)=(\d)", "$2:$1", "a=1 b=2", "1:a 2:b", vec![
+            matchtype!(Match "1:a"),
+            matchtype!(Normal " "),
+            matchtype!(Match "2:b"),
+        ]),
+        replace_with_named_group : (r"(?P<user>\w+)@\w+", "<${user}>", "mail alice@host now", "mail <alice> now", vec![
+            matchtype!(Normal "mail "),
+            matchtype!(Match "<alice>"),
+            matchtype!(Normal " now"),
+        ]),
+        replace_with_nothing_drops_match : (r"\d+", "", "a1b22c", "abc", vec![
+            matchtype!(Normal "a"),
+            matchtype!(Normal "b"),
+            matchtype!(Normal "c"),
+        ]),
+        replace_unknown_group_with_nothing : (r"(\w)", "[$9]", "ab", "[][]", vec![
+            matchtype!(Match "[]"),
+            matchtype!(Match "[]"),
+        ]),
+    }
+
+    macro_rules! test_records {
+        ($($func_name:ident: $values:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    // Given
+                    let (layout, full_text, re, expected) = $values;
+                    let re = Regex::new(re).unwrap();
+                    // When
+                    let actual = MatchSet::from(full_text, &re).to_records(layout);
+                    // Then
+                    let expected: Vec<Vec<String>> = expected
+                        .iter()
+                        .map(|row: &Vec<&str>| row.iter().map(|s| s.to_string()).collect())
+                        .collect();
+                    assert_eq!(expected, actual)
+                }
+            )*
+        }
+    }
+
+    test_records! {
+        given_rows_layout_then_return_one_record_per_match : (RecordLayout::Rows, "took 12ms, then 345ms", r"(\d+)(ms)", vec![
+            vec!["12", "ms"],
+            vec!["345", "ms"],
+        ]),
+        given_rows_layout_and_unmatched_group_then_return_empty_string : (RecordLayout::Rows, "bleble", r"(lala)?(bleble)", vec![
+            vec!["", "bleble"],
+        ]),
+        given_joined_layout_then_return_single_record : (RecordLayout::Joined, "took 12ms, then 345ms", r"(\d+)(ms)", vec![
+            vec!["12;345", "ms;ms"],
+        ]),
+        given_no_groups_then_return_whole_match : (RecordLayout::Rows, "took 12ms, then 345ms", r"\d+ms", vec![
+            vec!["12ms"],
+            vec!["345ms"],
+        ]),
+        given_no_groups_and_joined_layout_then_join_whole_matches : (RecordLayout::Joined, "took 12ms, then 345ms", r"\d+ms", vec![
+            vec!["12ms;345ms"],
+        ]),
+    }
+
+    #[test]
+    fn bytes_that_are_not_utf8_can_be_matched() {
+        let re = Regex::new(r"(?-u:\xff)(\w+)").unwrap();
+        let match_set = MatchSet {
+            full_text: b"a \xffbc",
+            re: &re,
+            origin: None,
+        };
+        assert_eq!(
+            vec![
+                matchtype!(Normal "a "),
+                matchtype!(Match "\u{fffd}"),
+                matchtype!(Group "bc", 1),
+            ],
+            match_set.to_matchtypes()
+        );
+        assert_eq!(vec!["bc"], match_set.to_strings());
+        assert_eq!("a <bc>", match_set.replaced("<$1>"));
+    }
+
+    #[test]
+    fn filter_matches_keeps_position_and_can_invert() {
+        let re = Regex::new(r"\d").unwrap();
+        let lines = || {
+            crate::source::into_lines("app.log", "a1\nb\nc3")
+                .into_iter()
+                .map(Ok)
+        };
+        let positions = |invert| -> Vec<usize> {
+            filter_matches(lines(), &re, invert)
+                .map(|line| line.unwrap().0)
+                .collect()
+        };
+        assert_eq!(vec![0, 2], positions(false));
+        assert_eq!(vec![1], positions(true));
+    }
+
+    #[test]
+    fn group_names_fall_back_to_group_index() {
+        let re = Regex::new(r"(?P<user>\w+)@(\w+)(?:\.)(?P<tld>\w+)").unwrap();
+        assert_eq!(vec!["user", "group2", "tld"], group_names(&re));
+    }
+
+    #[test]
+    fn group_names_without_groups_name_the_whole_match() {
+        let re = Regex::new(r"\d+ms").unwrap();
+        assert_eq!(vec!["match"], group_names(&re));
     }
 }
@@ -1,26 +1,29 @@
+#[cfg(test)]
 use std::fmt::{self, Display};
 
+#[cfg(test)]
 use colored::Colorize;
 use tui::{
-    style::{Color, Style},
+    style::{Color, Modifier, Style},
     text::{Span, Spans},
 };
 
-use crate::capture::{MatchSet, MatchType};
-pub trait Colorized {
-    fn highlight(&self) -> String;
-}
+use regex::bytes::Regex;
 
+use crate::capture::{group_names, MatchSet, MatchType};
 pub trait Styled {
-    fn style(&self) -> Spans;
+    fn style(&self) -> Spans<'_>;
 }
 
+/// Text styled as a whole, only used by tests since lines are styled per capture group
+#[cfg(test)]
 #[derive(Debug, PartialEq, Eq)]
 pub enum ColorStyle {
     Normal(String),
     Highlight(String),
 }
 
+#[cfg(test)]
 impl Display for ColorStyle {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
@@ -30,16 +33,10 @@ impl Display for ColorStyle {
     }
 }
 
-impl Colorized for Vec<ColorStyle> {
-    fn highlight(&self) -> String {
-        self.iter()
-            .fold("".to_string(), |s, color| format!("{}{}", s, color))
-    }
-}
-
+#[cfg(test)]
 impl Styled for Vec<ColorStyle> {
-    fn style(&self) -> Spans {
-        let hs = vec![Color::Yellow, Color::Blue, Color::Red];
+    fn style(&self) -> Spans<'_> {
+        let hs = [Color::Yellow, Color::Blue, Color::Red];
         let mut highlight_styles = hs.iter().cycle();
 
         let spans: Vec<Span> = self
@@ -63,36 +60,87 @@ impl Styled for Vec<ColorStyle> {
     }
 }
 
-impl Styled for MatchSet<'_> {
-    fn style(&self) -> Spans {
-        let hs = vec![Color::Yellow, Color::Blue, Color::Red];
-        let mut highlight_styles = hs.iter().cycle();
+/// Style of text matched by the whole pattern, outside of any capture group
+pub fn match_style() -> Style {
+    Style::default().fg(Color::Green)
+}
 
-        let spans: Vec<Span> = self
-            .to_matchtypes()
-            .iter()
-            .map(|color_style| match color_style {
-                MatchType::Normal(s) => Span::raw(s.to_string()),
-                MatchType::Group(s) => {
-                    let style = match highlight_styles.next().unwrap() {
-                        Color::Red => Style::default().fg(Color::Red),
-                        Color::Yellow => Style::default().fg(Color::Yellow),
-                        Color::Blue => Style::default().fg(Color::Blue),
-                        _ => Style::default().fg(Color::Green),
-                    };
-                    Span::styled(s.to_string(), style)
-                }
-            })
-            .collect();
-        Spans::from(spans)
+/// Style of the file and line number shown before each line
+pub fn gutter_style() -> Style {
+    Style::default().fg(Color::DarkGray)
+}
+
+/// Colors cycled through by capture group index
+const GROUP_COLORS: [Color; 3] = [Color::Yellow, Color::Blue, Color::Red];
+
+/// Style of a segment inside capture groups. The innermost group decides the color,
+/// while text inside nested groups is underlined so the enclosing group stays visible
+pub fn group_style(layers: &[usize]) -> Style {
+    let innermost = layers.last().copied().unwrap_or(1);
+    let style = Style::default().fg(GROUP_COLORS[(innermost.max(1) - 1) % GROUP_COLORS.len()]);
+    match layers.len() {
+        0..=1 => style,
+        2 => style.add_modifier(Modifier::UNDERLINED),
+        _ => style.add_modifier(Modifier::UNDERLINED | Modifier::BOLD),
+    }
+}
+
+/// Names of the capture groups in `re`, each shown in the color of its group
+pub fn legend(re: &Regex) -> Spans<'static> {
+    let mut spans = Vec::new();
+    for (i, name) in group_names(re).into_iter().enumerate() {
+        if i > 0 {
+            spans.push(Span::raw(" "));
+        }
+        spans.push(Span::styled(name, group_style(&[i + 1])));
+    }
+    Spans::from(spans)
+}
+
+fn spans_of(match_types: Vec<MatchType>) -> Spans<'static> {
+    let spans: Vec<Span> = match_types
+        .into_iter()
+        .map(|match_type| match match_type {
+            MatchType::Normal(s) => Span::raw(s),
+            MatchType::Match(s) => Span::styled(s, match_style()),
+            MatchType::Group { text, layers, .. } => Span::styled(text, group_style(&layers)),
+        })
+        .collect();
+    Spans::from(spans)
+}
+
+/// Splits `spans` at every newline, keeping the style of each part, so that a record
+/// of several lines is shown on as many rows
+pub fn split_rows(spans: Spans<'_>) -> Vec<Spans<'static>> {
+    let mut rows = vec![Spans(Vec::new())];
+    for span in spans.0 {
+        for (i, part) in span.content.split('\n').enumerate() {
+            if i > 0 {
+                rows.push(Spans(Vec::new()));
+            }
+            if !part.is_empty() {
+                let row = rows.last_mut().unwrap();
+                row.0.push(Span::styled(part.to_string(), span.style));
+            }
+        }
     }
+    rows
+}
+
+impl Styled for MatchSet<'_> {
+    fn style(&self) -> Spans<'_> {
+        spans_of(self.to_matchtypes())
+    }
+}
+
+/// Line with its matches replaced by `template`, the inserted text styled as a match
+pub fn style_replaced(match_set: &MatchSet, template: &str) -> Spans<'static> {
+    spans_of(match_set.to_replaced_matchtypes(template))
 }
 
 #[cfg(test)]
 #[allow(non_snake_case)]
 mod tests {
-    use regex::Regex;
-
     use super::*;
 
     macro_rules! colorstyle {
@@ -101,6 +149,106 @@ mod tests {
         };
     }
 
+    #[test]
+    fn givenNestedGroups_whenStyled_thenUnderlineInnerGroupsInTheirOwnColor() {
+        // Given
+        let re = Regex::new(r"((\w+)@(\w+))").unwrap();
+        let match_set = MatchSet::from("alice@host", &re);
+        let underlined = Style::default().add_modifier(Modifier::UNDERLINED);
+        let expected = Spans::from(vec![
+            Span::styled("alice", underlined.fg(Color::Blue)),
+            Span::styled("@", Style::default().fg(Color::Yellow)),
+            Span::styled("host", underlined.fg(Color::Red)),
+        ]);
+        // When
+        let actual = match_set.style();
+        // Then
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    fn givenSameGroupInMultipleMatches_whenStyled_thenUseSameColor() {
+        // Given
+        let re = Regex::new(r"(\d+)ms").unwrap();
+        let match_set = MatchSet::from("1ms 2ms", &re);
+        let yellow = Style::default().fg(Color::Yellow);
+        let expected = Spans::from(vec![
+            Span::styled("1", yellow),
+            Span::styled("ms", match_style()),
+            Span::raw(" "),
+            Span::styled("2", yellow),
+            Span::styled("ms", match_style()),
+        ]);
+        // When
+        let actual = match_set.style();
+        // Then
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    fn givenNoCaptureGroups_whenStyled_thenHighlightWholeMatch() {
+        // Given
+        let re = Regex::new(r"\d{4}-\d{2}").unwrap();
+        let match_set = MatchSet::from("date 2021-03", &re);
+        let expected = Spans::from(vec![
+            Span::raw("date "),
+            Span::styled("2021-03", match_style()),
+        ]);
+        // When
+        let actual = match_set.style();
+        // Then
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    fn givenTemplate_whenStyleReplaced_thenHighlightReplacements() {
+        // Given
+        let re = Regex::new(r"(\w+)@(\w+)").unwrap();
+        let match_set = MatchSet::from("mail alice@host", &re);
+        let expected = Spans::from(vec![
+            Span::raw("mail "),
+            Span::styled("host/alice", match_style()),
+        ]);
+        // When
+        let actual = style_replaced(&match_set, "$2/$1");
+        // Then
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    fn givenSpansWithNewlines_whenSplitRows_thenKeepStyleOnEveryRow() {
+        // Given
+        let spans = Spans::from(vec![
+            Span::raw("at\n"),
+            Span::styled("foo\nbar", match_style()),
+            Span::raw(" end"),
+        ]);
+        let expected = vec![
+            Spans::from(vec![Span::raw("at")]),
+            Spans::from(vec![Span::styled("foo", match_style())]),
+            Spans::from(vec![Span::styled("bar", match_style()), Span::raw(" end")]),
+        ];
+        // When
+        let actual = split_rows(spans);
+        // Then
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    fn givenNamedAndUnnamedGroups_whenLegend_thenShowNamesInGroupColors() {
+        // Given
+        let re = Regex::new(r"(?P<user>\w+)@(\w+)").unwrap();
+        let expected = Spans::from(vec![
+            Span::styled("user", Style::default().fg(Color::Yellow)),
+            Span::raw(" "),
+            Span::styled("group2", Style::default().fg(Color::Blue)),
+        ]);
+        // When
+        let actual = legend(&re);
+        // Then
+        assert_eq!(expected, actual)
+    }
+
     #[test]
     fn display_colorstyle() {
         assert_eq!(
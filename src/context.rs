@@ -0,0 +1,221 @@
+use std::{collections::VecDeque, io, iter::Enumerate, ops::Range};
+
+use regex::bytes::Regex;
+
+use crate::source::Line;
+
+/// Number of lines shown around each match, like the `-B` and `-A` options of grep
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Context {
+    pub before: usize,
+    pub after: usize,
+}
+
+impl Context {
+    fn is_empty(&self) -> bool {
+        self.before == 0 && self.after == 0
+    }
+
+    /// Most rows a single match can take up
+    pub fn rows_per_match(&self) -> usize {
+        1 + self.before + self.after
+    }
+}
+
+/// A line to show, or a break between lines that are not next to each other
+#[derive(Debug, PartialEq)]
+pub enum Row<T> {
+    Match(T),
+    Context(T),
+    Break,
+}
+
+/// Rows showing the matched lines at `window` in `matched`, out of `len` lines, with
+/// the lines around them as context. Context never repeats a line, so it stops at the
+/// next match or at the context of the match before
+pub fn context_rows(
+    matched: &[usize],
+    window: Range<usize>,
+    context: Context,
+    len: usize,
+) -> Vec<Row<usize>> {
+    let mut rows = Vec::new();
+    // first line after what was shown so far
+    let mut shown: Option<usize> = None;
+    for k in window {
+        let line = matched[k];
+        let mut start = line.saturating_sub(context.before);
+        if k > 0 {
+            start = start.max(matched[k - 1] + 1);
+        }
+        if let Some(shown) = shown {
+            if start > shown && !context.is_empty() {
+                rows.push(Row::Break);
+            }
+            start = start.max(shown);
+        }
+        rows.extend((start..line).map(Row::Context));
+        rows.push(Row::Match(line));
+
+        let next = matched.get(k + 1).copied().unwrap_or(len);
+        let end = (line + 1 + context.after).min(next).min(len);
+        rows.extend((line + 1..end).map(Row::Context));
+        shown = Some(end);
+    }
+    rows
+}
+
+/// Streams the lines matching `re`, or with `invert` the lines that don't, with the
+/// lines around them as context. Only the lines before a match are held in memory
+pub struct ContextLines<'r, I> {
+    lines: Enumerate<I>,
+    re: &'r Regex,
+    invert: bool,
+    context: Context,
+    /// Lines that may turn out to be context before a match
+    before: VecDeque<(usize, Line)>,
+    /// Number of lines still to show as context after the last match
+    after: usize,
+    /// Position of the last line shown
+    shown: Option<usize>,
+    pending: VecDeque<Row<Line>>,
+}
+
+impl<'r, I> ContextLines<'r, I>
+where
+    I: Iterator<Item = io::Result<Line>>,
+{
+    pub fn new(lines: I, re: &'r Regex, invert: bool, context: Context) -> Self {
+        ContextLines {
+            lines: lines.enumerate(),
+            re,
+            invert,
+            context,
+            before: VecDeque::new(),
+            after: 0,
+            shown: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn show(&mut self, i: usize, row: Row<Line>) {
+        if let Some(shown) = self.shown {
+            if i > shown + 1 && !self.context.is_empty() {
+                self.pending.push_back(Row::Break);
+            }
+        }
+        self.pending.push_back(row);
+        self.shown = Some(i);
+    }
+}
+
+impl<'r, I> Iterator for ContextLines<'r, I>
+where
+    I: Iterator<Item = io::Result<Line>>,
+{
+    type Item = io::Result<Row<Line>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            let (i, line) = match self.lines.next()? {
+                (i, Ok(line)) => (i, line),
+                (_, Err(err)) => return Some(Err(err)),
+            };
+            if self.re.is_match(&line.bytes) != self.invert {
+                while let Some((j, before)) = self.before.pop_front() {
+                    self.show(j, Row::Context(before));
+                }
+                self.show(i, Row::Match(line));
+                self.after = self.context.after;
+            } else if self.after > 0 {
+                self.show(i, Row::Context(line));
+                self.after -= 1;
+            } else if self.context.before > 0 {
+                if self.before.len() == self.context.before {
+                    self.before.pop_front();
+                }
+                self.before.push_back((i, line));
+            }
+        }
+        self.pending.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::into_lines;
+
+    fn context(before: usize, after: usize) -> Context {
+        Context { before, after }
+    }
+
+    /// Rows as text, with context in parentheses
+    fn texts(rows: Vec<Row<String>>) -> Vec<String> {
+        rows.into_iter()
+            .map(|row| match row {
+                Row::Match(text) => text,
+                Row::Context(text) => format!("({})", text),
+                Row::Break => "--".to_string(),
+            })
+            .collect()
+    }
+
+    macro_rules! test_context {
+        ($($func_name:ident: $values:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    // Given
+                    let (re, invert, context, content, expected): (&str, bool, Context, &str, Vec<&str>) = $values;
+                    let re = Regex::new(re).unwrap();
+                    let lines = into_lines("app.log", content);
+                    let matched: Vec<usize> = lines
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, line)| re.is_match(&line.bytes) != invert)
+                        .map(|(i, _)| i)
+                        .collect();
+                    // When
+                    let streamed: Vec<Row<String>> =
+                        ContextLines::new(lines.clone().into_iter().map(Ok), &re, invert, context)
+                            .map(|row| match row.unwrap() {
+                                Row::Match(line) => Row::Match(line.text().into_owned()),
+                                Row::Context(line) => Row::Context(line.text().into_owned()),
+                                Row::Break => Row::Break,
+                            })
+                            .collect();
+                    let indexed: Vec<Row<String>> =
+                        context_rows(&matched, 0..matched.len(), context, lines.len())
+                            .into_iter()
+                            .map(|row| match row {
+                                Row::Match(i) => Row::Match(lines[i].text().into_owned()),
+                                Row::Context(i) => Row::Context(lines[i].text().into_owned()),
+                                Row::Break => Row::Break,
+                            })
+                            .collect();
+                    // Then
+                    assert_eq!(expected, texts(streamed));
+                    assert_eq!(expected, texts(indexed));
+                }
+            )*
+        }
+    }
+
+    test_context! {
+        without_context_only_matches_are_shown : (r"\d", false, context(0, 0), "a\n1\nb\n2", vec!["1", "2"]),
+        inverted_shows_lines_not_matching : (r"\d", true, context(0, 0), "a\n1\nb\n2", vec!["a", "b"]),
+        context_before_and_after : (r"\d", false, context(1, 1), "a\nb\n1\nc\nd", vec!["(b)", "1", "(c)"]),
+        context_stops_at_start_and_end : (r"\d", false, context(2, 2), "1\na", vec!["1", "(a)"]),
+        distant_matches_are_broken_apart : (r"\d", false, context(0, 1), "1\na\nb\n2\nc", vec!["1", "(a)", "--", "2", "(c)"]),
+        touching_context_is_not_broken_apart : (r"\d", false, context(1, 1), "1\na\nb\n2", vec!["1", "(a)", "(b)", "2"]),
+        context_is_not_repeated_between_close_matches : (r"\d", false, context(2, 2), "1\na\n2\nb", vec!["1", "(a)", "2", "(b)"]),
+        inverted_with_context_shows_matches_as_context : (r"\d", true, context(1, 0), "1\na\n2\n3\nb", vec!["(1)", "a", "--", "(3)", "b"]),
+    }
+
+    #[test]
+    fn rows_of_window_start_after_the_match_before() {
+        let rows = context_rows(&[1, 3], 1..2, context(2, 0), 5);
+        assert_eq!(vec![Row::Context(2), Row::Match(3)], rows);
+    }
+}
@@ -0,0 +1,55 @@
+use std::{env, ffi::OsString, path::PathBuf};
+
+/// Directory ire keeps its data in, such as the pattern history
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir(
+        env::var_os("XDG_DATA_HOME"),
+        env::var_os("HOME"),
+        ".local/share",
+    )
+}
+
+/// Directory ire reads its configuration from, such as the pattern library
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir(
+        env::var_os("XDG_CONFIG_HOME"),
+        env::var_os("HOME"),
+        ".config",
+    )
+}
+
+/// The `ire` directory under `base`, following the XDG base directory spec, or under
+/// `default` in the home directory when `base` is not set
+fn xdg_dir(base: Option<OsString>, home: Option<OsString>, default: &str) -> Option<PathBuf> {
+    let base = match base.map(PathBuf::from) {
+        // relative paths are to be ignored, per the spec
+        Some(dir) if dir.is_absolute() => dir,
+        _ => PathBuf::from(home?).join(default),
+    };
+    Some(base.join("ire"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dir_follows_xdg_and_falls_back_to_home() {
+        let dir = |base: Option<&str>, home: Option<&str>| {
+            xdg_dir(
+                base.map(OsString::from),
+                home.map(OsString::from),
+                ".local/share",
+            )
+        };
+        assert_eq!(
+            Some(PathBuf::from("/data/ire")),
+            dir(Some("/data"), Some("/home/me"))
+        );
+        assert_eq!(
+            Some(PathBuf::from("/home/me/.local/share/ire")),
+            dir(Some("relative"), Some("/home/me"))
+        );
+        assert_eq!(None, dir(None, None));
+    }
+}
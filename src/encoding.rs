@@ -0,0 +1,210 @@
+use std::io::{self, Read};
+
+/// Encoding of the input, which is decoded to UTF-8 while reading. Bytes that are not
+/// valid in the encoding are replaced rather than failing the whole input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Latin1,
+    /// Byte order given by a byte order mark, little endian without one
+    Utf16,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    /// Encoding of a `--encoding` value
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name {
+            "utf-8" => Some(Encoding::Utf8),
+            "latin1" => Some(Encoding::Latin1),
+            "utf-16" => Some(Encoding::Utf16),
+            "utf-16le" => Some(Encoding::Utf16Le),
+            "utf-16be" => Some(Encoding::Utf16Be),
+            _ => None,
+        }
+    }
+
+    fn is_utf16(self) -> bool {
+        matches!(
+            self,
+            Encoding::Utf16 | Encoding::Utf16Le | Encoding::Utf16Be
+        )
+    }
+
+    /// Whether the start of a file, `head`, looks like binary data rather than text.
+    /// Like grep, text is taken to never hold NUL bytes, which UTF-16 text does
+    pub fn is_binary(self, head: &[u8]) -> bool {
+        !self.is_utf16() && head.contains(&0)
+    }
+
+    /// `reader` with its content decoded to UTF-8. UTF-8 is passed through as is, and
+    /// decoded lossily when read
+    pub fn decode<R: Read + 'static>(self, reader: R) -> Box<dyn Read> {
+        match self {
+            Encoding::Utf8 => Box::new(reader),
+            _ => Box::new(Decoder::new(reader, self)),
+        }
+    }
+}
+
+/// Reads text in an `Encoding` as UTF-8
+pub struct Decoder<R> {
+    reader: R,
+    encoding: Encoding,
+    /// Bytes read that don't make up a whole character yet
+    pending: Vec<u8>,
+    /// Decoded text, of which `pos` bytes have been read
+    decoded: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(reader: R, encoding: Encoding) -> Self {
+        Decoder {
+            reader,
+            encoding,
+            pending: Vec::new(),
+            decoded: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Decodes the whole characters in `pending`, or everything left at the end of
+    /// the input
+    fn decode(&mut self, eof: bool) {
+        self.decoded.drain(..self.pos);
+        self.pos = 0;
+        let mut text = String::new();
+        match self.encoding {
+            // invalid bytes are kept, a pattern can still match them
+            Encoding::Utf8 => self.decoded.append(&mut self.pending),
+            // every byte is the code point of the same value
+            Encoding::Latin1 => text.extend(self.pending.drain(..).map(char::from)),
+            Encoding::Utf16 => {
+                if self.pending.len() < 2 && !eof {
+                    return;
+                }
+                self.encoding = match self.pending[..] {
+                    [0xFE, 0xFF, ..] => Encoding::Utf16Be,
+                    _ => Encoding::Utf16Le,
+                };
+                if let [0xFE, 0xFF, ..] | [0xFF, 0xFE, ..] = self.pending[..] {
+                    self.pending.drain(..2);
+                }
+                return self.decode(eof);
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let big_endian = self.encoding == Encoding::Utf16Be;
+                let mut units: Vec<u16> = self
+                    .pending
+                    .chunks_exact(2)
+                    .map(|pair| match big_endian {
+                        true => u16::from_be_bytes([pair[0], pair[1]]),
+                        false => u16::from_le_bytes([pair[0], pair[1]]),
+                    })
+                    .collect();
+                let mut used = units.len() * 2;
+                // the rest of a surrogate pair may still be coming
+                if !eof && matches!(units.last(), Some(0xD800..=0xDBFF)) {
+                    units.pop();
+                    used -= 2;
+                }
+                text.extend(
+                    char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)),
+                );
+                self.pending.drain(..used);
+                if eof && !self.pending.is_empty() {
+                    // a lone byte at the end
+                    text.push(char::REPLACEMENT_CHARACTER);
+                    self.pending.clear();
+                }
+            }
+        }
+        self.decoded.extend(text.bytes());
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut raw = [0; 8 * 1024];
+        while self.pos == self.decoded.len() {
+            let n = self.reader.read(&mut raw)?;
+            self.pending.extend_from_slice(&raw[..n]);
+            self.decode(n == 0);
+            if n == 0 {
+                break;
+            }
+        }
+        let n = buf.len().min(self.decoded.len() - self.pos);
+        buf[..n].copy_from_slice(&self.decoded[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads one byte at a time, so characters are split across reads
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((&byte, rest)), Some(first)) => {
+                    *first = byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    macro_rules! test_decode {
+        ($($func_name:ident: $values:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    let (encoding, bytes, expected): (Encoding, &[u8], &str) = $values;
+                    let mut whole = String::new();
+                    Decoder::new(bytes, encoding).read_to_string(&mut whole).unwrap();
+                    assert_eq!(expected, whole);
+                    let mut trickled = String::new();
+                    Decoder::new(Trickle(bytes), encoding).read_to_string(&mut trickled).unwrap();
+                    assert_eq!(expected, trickled);
+                }
+            )*
+        }
+    }
+
+    test_decode! {
+        latin1_bytes_are_code_points : (Encoding::Latin1, b"caf\xe9 \xff\n", "café ÿ\n"),
+        utf16le : (Encoding::Utf16Le, b"a\x00\n\x00\xe9\x00", "a\né"),
+        utf16be : (Encoding::Utf16Be, b"\x00a\x00\n", "a\n"),
+        utf16_without_bom_is_little_endian : (Encoding::Utf16, b"a\x00b\x00", "ab"),
+        utf16_bom_gives_byte_order : (Encoding::Utf16, b"\xfe\xff\x00a\x00b", "ab"),
+        utf16_bom_is_removed : (Encoding::Utf16, b"\xff\xfea\x00", "a"),
+        utf16_surrogate_pairs : (Encoding::Utf16Le, b"\x3d\xd8\x00\xde!\x00", "😀!"),
+        utf16_lone_surrogate_is_replaced : (Encoding::Utf16Le, b"\x3d\xd8a\x00", "\u{fffd}a"),
+        utf16_odd_byte_at_end_is_replaced : (Encoding::Utf16Le, b"a\x00b", "a\u{fffd}"),
+        empty_input : (Encoding::Utf16, b"", ""),
+        utf8_is_passed_through : (Encoding::Utf8, "é".as_bytes(), "é"),
+    }
+
+    #[test]
+    fn nul_bytes_are_binary_unless_utf16() {
+        assert!(Encoding::Utf8.is_binary(b"ELF\x00\x01"));
+        assert!(Encoding::Latin1.is_binary(b"\x00"));
+        assert!(!Encoding::Utf8.is_binary("text é".as_bytes()));
+        assert!(!Encoding::Utf16.is_binary(b"a\x00"));
+    }
+
+    #[test]
+    fn names_of_encodings() {
+        assert_eq!(Some(Encoding::Utf16Be), Encoding::from_name("utf-16be"));
+        assert_eq!(None, Encoding::from_name("ebcdic"));
+    }
+}
@@ -0,0 +1,168 @@
+use std::{error::Error, fmt, io};
+
+use crate::pattern::PatternError;
+use crate::source::ReadError;
+
+/// Exit code when nothing matched, like grep
+pub const EXIT_NO_MATCH: i32 = 1;
+/// Exit code when reading or writing failed, or an option is wrong, like grep
+pub const EXIT_ERROR: i32 = 2;
+/// Exit code when the ui is quit without writing the result
+pub const EXIT_QUIT: i32 = 3;
+
+/// Why ire stopped without writing a result
+#[derive(Debug)]
+pub enum IreError {
+    /// An option is unknown, missing or has a wrong value
+    Args(clap::Error),
+    /// Neither a file, a glob or piped data was given
+    NoInput,
+    /// Reading the input at `path` failed
+    Read { path: String, source: io::Error },
+    /// Using the terminal failed
+    Io(io::Error),
+    /// A regex given as an option doesn't compile
+    Regex(regex::Error),
+    /// The `--pattern` doesn't compile
+    Pattern(PatternError),
+    /// The pattern library at `path` can't be read
+    Library {
+        path: String,
+        source: Box<dyn Error>,
+    },
+    /// The `--glob` pattern is not a valid glob
+    Glob(glob::PatternError),
+    /// The result couldn't be written to `path`, or stdout when there is none
+    Write {
+        path: Option<String>,
+        source: Box<dyn Error>,
+    },
+    /// The ui was quit with `q`
+    Quit,
+}
+
+impl IreError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            IreError::Quit => EXIT_QUIT,
+            _ => EXIT_ERROR,
+        }
+    }
+
+    /// Error for reading the input that failed with `err`, naming the input when `err`
+    /// holds a `ReadError`
+    pub fn read(err: io::Error) -> Self {
+        if !err.get_ref().is_some_and(|inner| inner.is::<ReadError>()) {
+            return IreError::Read {
+                path: "input".to_string(),
+                source: err,
+            };
+        }
+        let read = err.into_inner().unwrap().downcast::<ReadError>().unwrap();
+        IreError::Read {
+            path: read.path,
+            source: read.source,
+        }
+    }
+
+    /// Error for writing the result to `path` that failed with `source`
+    pub fn write(path: Option<&str>, source: impl Into<Box<dyn Error>>) -> Self {
+        IreError::Write {
+            path: path.map(String::from),
+            source: source.into(),
+        }
+    }
+}
+
+impl fmt::Display for IreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IreError::Args(err) => write!(f, "{}", err.message),
+            IreError::NoInput => write!(
+                f,
+                "No input: give a FILENAME, a --glob, or pipe data to stdin"
+            ),
+            IreError::Read { path, source } => write!(f, "Unable to read {}: {}", path, source),
+            IreError::Io(err) => write!(f, "{}", err),
+            IreError::Regex(err) => write!(f, "Invalid regex: {}", err),
+            IreError::Pattern(err) => write!(f, "Invalid pattern: {}", err),
+            IreError::Library { path, source } => {
+                write!(f, "Unable to read pattern library {}: {}", path, source)
+            }
+            IreError::Glob(err) => write!(f, "Invalid glob: {}", err),
+            IreError::Write {
+                path: Some(path),
+                source,
+            } => {
+                write!(f, "Unable to write to {}: {}", path, source)
+            }
+            IreError::Write { path: None, source } => {
+                write!(f, "Unable to write result: {}", source)
+            }
+            IreError::Quit => write!(f, "Exited without writing result"),
+        }
+    }
+}
+
+impl Error for IreError {}
+
+impl From<clap::Error> for IreError {
+    fn from(err: clap::Error) -> Self {
+        IreError::Args(err)
+    }
+}
+
+impl From<io::Error> for IreError {
+    fn from(err: io::Error) -> Self {
+        IreError::Io(err)
+    }
+}
+
+impl From<regex::Error> for IreError {
+    fn from(err: regex::Error) -> Self {
+        IreError::Regex(err)
+    }
+}
+
+impl From<PatternError> for IreError {
+    fn from(err: PatternError) -> Self {
+        IreError::Pattern(err)
+    }
+}
+
+impl From<glob::PatternError> for IreError {
+    fn from(err: glob::PatternError) -> Self {
+        IreError::Glob(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quitting_has_its_own_exit_code() {
+        assert_eq!(EXIT_QUIT, IreError::Quit.exit_code());
+        assert_eq!(EXIT_ERROR, IreError::NoInput.exit_code());
+        assert_eq!(
+            EXIT_ERROR,
+            IreError::read(io::Error::other("broken")).exit_code()
+        );
+    }
+
+    #[test]
+    fn read_error_names_the_input() {
+        let err = IreError::read(ReadError::wrap("app.log", io::Error::other("broken")));
+        assert_eq!("Unable to read app.log: broken", err.to_string());
+        let err = IreError::read(io::Error::other("broken"));
+        assert_eq!("Unable to read input: broken", err.to_string());
+    }
+
+    #[test]
+    fn write_error_names_the_output() {
+        let err = IreError::write(Some("out.csv"), io::Error::other("denied"));
+        assert_eq!("Unable to write to out.csv: denied", err.to_string());
+        let err = IreError::write(None, io::Error::other("closed"));
+        assert_eq!("Unable to write result: closed", err.to_string());
+    }
+}
@@ -10,18 +10,30 @@ use std::time::Duration;
 use termion::event::Key;
 use termion::input::TermRead;
 
+use crate::follow::Follow;
+use crate::search::MatchUpdate;
+use crate::source::Line;
+
 pub enum Event<I> {
     Input(I),
     Tick,
+    /// Lines added to a followed input
+    Lines(Vec<Line>),
+    /// Progress of a search, see `Searcher`
+    Matches(MatchUpdate),
+    /// A line could not be read while searching
+    Error(io::Error),
 }
 
 /// A small event handler that wrap termion input and tick events. Each event
 /// type is handled in its own thread and returned to a common `Receiver`
 pub struct Events {
     rx: mpsc::Receiver<Event<Key>>,
-    input_handle: thread::JoinHandle<()>,
+    tx: mpsc::Sender<Event<Key>>,
+    tick_rate: Duration,
+    _input_handle: thread::JoinHandle<()>,
     ignore_exit_key: Arc<AtomicBool>,
-    tick_handle: thread::JoinHandle<()>,
+    _tick_handle: thread::JoinHandle<()>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -51,21 +63,28 @@ impl Events {
             let tx = tx.clone();
             let ignore_exit_key = ignore_exit_key.clone();
             thread::spawn(move || {
-                let stdin = io::stdin();
-                for evt in stdin.keys() {
-                    if let Ok(key) = evt {
-                        if let Err(err) = tx.send(Event::Input(key)) {
-                            eprintln!("{}", err);
-                            return;
-                        }
-                        if !ignore_exit_key.load(Ordering::Relaxed) && key == config.exit_key {
-                            return;
-                        }
+                // keys are read from the terminal rather than stdin, which may be a pipe
+                // holding the input data
+                let tty = match termion::get_tty() {
+                    Ok(tty) => tty,
+                    Err(err) => {
+                        eprintln!("{}", err);
+                        return;
+                    }
+                };
+                for key in tty.keys().flatten() {
+                    if let Err(err) = tx.send(Event::Input(key)) {
+                        eprintln!("{}", err);
+                        return;
+                    }
+                    if !ignore_exit_key.load(Ordering::Relaxed) && key == config.exit_key {
+                        return;
                     }
                 }
             })
         };
         let tick_handle = {
+            let tx = tx.clone();
             thread::spawn(move || loop {
                 if tx.send(Event::Tick).is_err() {
                     break;
@@ -76,12 +95,40 @@ impl Events {
 
         Events {
             rx,
+            tx,
+            tick_rate: config.tick_rate,
             ignore_exit_key,
-            input_handle,
-            tick_handle,
+            _input_handle: input_handle,
+            _tick_handle: tick_handle,
         }
     }
 
+    /// Polls `followers` once per tick, sending any new lines as `Event::Lines`
+    pub fn follow(&self, mut followers: Vec<Box<dyn Follow>>) {
+        let tx = self.tx.clone();
+        let tick_rate = self.tick_rate;
+        thread::spawn(move || loop {
+            for follower in followers.iter_mut() {
+                match follower.poll() {
+                    Ok(lines) if lines.is_empty() => {}
+                    Ok(lines) => {
+                        if tx.send(Event::Lines(lines)).is_err() {
+                            return;
+                        }
+                    }
+                    // keep following, the file may come back
+                    Err(_) => {}
+                }
+            }
+            thread::sleep(tick_rate);
+        });
+    }
+
+    /// Sender for events produced outside of `Events`
+    pub fn sender(&self) -> mpsc::Sender<Event<Key>> {
+        self.tx.clone()
+    }
+
     pub fn next(&self) -> Result<Event<Key>, mpsc::RecvError> {
         self.rx.recv()
     }
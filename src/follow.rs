@@ -0,0 +1,278 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, Read, Seek, SeekFrom},
+    os::unix::fs::MetadataExt,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use crate::encoding::Encoding;
+use crate::source::{glob_paths, line_bytes, Line, Origin, ReadError, Source, STDIN_PATH};
+
+/// An input that keeps growing, polled for new lines
+pub trait Follow: Send {
+    /// Returns the complete lines added since the last call
+    fn poll(&mut self) -> io::Result<Vec<Line>>;
+}
+
+/// Followers for every file in `source`, or stdin
+pub fn followers_of(source: &Source) -> Vec<Box<dyn Follow>> {
+    match source {
+        Source::File(filename) => vec![Box::new(FileFollower::new(filename))],
+        Source::Glob(pattern) => glob_paths(pattern, Encoding::Utf8)
+            .iter()
+            .map(|path| Box::new(FileFollower::new(&path.to_string_lossy())) as Box<dyn Follow>)
+            .collect(),
+        Source::Stdin => vec![Box::new(StdinFollower::new())],
+    }
+}
+
+/// Follows a file like `tail -F`. When the file is truncated it is read again from
+/// the start, and when it is replaced, e.g. by log rotation, the new file is opened
+pub struct FileFollower {
+    path: Arc<str>,
+    file: Option<File>,
+    inode: u64,
+    offset: u64,
+    line_number: usize,
+    /// Bytes after the last newline, waiting for the rest of their line
+    partial: Vec<u8>,
+}
+
+impl FileFollower {
+    /// Follows `path` from the start of the file, so the first poll returns the
+    /// current content
+    pub fn new(path: &str) -> Self {
+        FileFollower {
+            path: Arc::from(path),
+            file: None,
+            inode: 0,
+            offset: 0,
+            line_number: 0,
+            partial: Vec::new(),
+        }
+    }
+
+    fn restart(&mut self, file: Option<File>, inode: u64) {
+        self.file = file;
+        self.inode = inode;
+        self.offset = 0;
+        self.line_number = 0;
+        self.partial.clear();
+    }
+
+    /// Lines added since the last call, reopening the file when it was rotated
+    fn read_new(&mut self) -> io::Result<Vec<Line>> {
+        let metadata = match fs::metadata(&*self.path) {
+            Ok(metadata) => metadata,
+            // the file may be briefly missing while it is rotated
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err),
+        };
+
+        let mut lines = Vec::new();
+        if self.file.is_none() || metadata.ino() != self.inode {
+            // finish what was written to the old file before switching
+            lines.extend(self.read_to_end()?);
+            self.restart(Some(File::open(&*self.path)?), metadata.ino());
+        } else if metadata.len() < self.offset {
+            let file = self.file.take();
+            self.restart(file, metadata.ino());
+        }
+        lines.extend(self.read_to_end()?);
+        Ok(lines)
+    }
+
+    fn read_to_end(&mut self) -> io::Result<Vec<Line>> {
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => return Ok(Vec::new()),
+        };
+        file.seek(SeekFrom::Start(self.offset))?;
+        self.offset += file.read_to_end(&mut self.partial)? as u64;
+
+        let mut lines = Vec::new();
+        while let Some(end) = self.partial.iter().position(|&b| b == b'\n') {
+            let bytes = line_bytes(&self.partial[..end]);
+            self.partial.drain(..=end);
+            self.line_number += 1;
+            lines.push(Line {
+                bytes,
+                origin: Origin {
+                    path: self.path.clone(),
+                    line_number: self.line_number,
+                },
+            });
+        }
+        Ok(lines)
+    }
+}
+
+impl Follow for FileFollower {
+    fn poll(&mut self) -> io::Result<Vec<Line>> {
+        self.read_new()
+            .map_err(|err| ReadError::wrap(&self.path, err))
+    }
+}
+
+/// Follows stdin, e.g. `kubectl logs -f`. Lines are read in a separate thread, since
+/// reading from stdin blocks until data arrives
+pub struct StdinFollower {
+    buffer: Arc<Mutex<Vec<Vec<u8>>>>,
+    line_number: usize,
+    path: Arc<str>,
+}
+
+impl StdinFollower {
+    pub fn new() -> Self {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        {
+            let buffer = buffer.clone();
+            thread::spawn(move || read_lines(io::stdin().lock(), &buffer));
+        }
+        StdinFollower {
+            buffer,
+            line_number: 0,
+            path: Arc::from(STDIN_PATH),
+        }
+    }
+}
+
+/// Pushes the lines of `reader` to `buffer` until it ends. Bytes that are not valid
+/// UTF-8 are kept, like in `FileFollower`, rather than ending the reading
+fn read_lines(mut reader: impl BufRead, buffer: &Mutex<Vec<Vec<u8>>>) {
+    let mut line = Vec::new();
+    while matches!(reader.read_until(b'\n', &mut line), Ok(read) if read > 0) {
+        let bytes = line_bytes(line.strip_suffix(b"\n").unwrap_or(&line));
+        buffer.lock().unwrap().push(bytes);
+        line.clear();
+    }
+}
+
+impl Follow for StdinFollower {
+    fn poll(&mut self) -> io::Result<Vec<Line>> {
+        let lines: Vec<Vec<u8>> = self.buffer.lock().unwrap().drain(..).collect();
+        Ok(lines
+            .into_iter()
+            .map(|bytes| {
+                self.line_number += 1;
+                Line {
+                    bytes,
+                    origin: Origin {
+                        path: self.path.clone(),
+                        line_number: self.line_number,
+                    },
+                }
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("ire_follow_{}_{}", std::process::id(), name));
+        path.to_string_lossy().to_string()
+    }
+
+    fn append(path: &str, text: &str) {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    fn texts(lines: Vec<Line>) -> Vec<(String, usize)> {
+        lines
+            .into_iter()
+            .map(|line| (line.text().into_owned(), line.origin.line_number))
+            .collect()
+    }
+
+    #[test]
+    fn first_poll_returns_content_and_later_polls_appended_lines() {
+        let path = temp_path("append");
+        fs::write(&path, "one\ntwo\n").unwrap();
+        let mut follower = FileFollower::new(&path);
+
+        assert_eq!(
+            vec![("one".to_string(), 1), ("two".to_string(), 2)],
+            texts(follower.poll().unwrap())
+        );
+        assert!(follower.poll().unwrap().is_empty());
+
+        append(&path, "three\n");
+        assert_eq!(
+            vec![("three".to_string(), 3)],
+            texts(follower.poll().unwrap())
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn partial_line_is_held_back_until_completed() {
+        let path = temp_path("partial");
+        fs::write(&path, "one\ntw").unwrap();
+        let mut follower = FileFollower::new(&path);
+
+        assert_eq!(
+            vec![("one".to_string(), 1)],
+            texts(follower.poll().unwrap())
+        );
+        append(&path, "o\n");
+        assert_eq!(
+            vec![("two".to_string(), 2)],
+            texts(follower.poll().unwrap())
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn truncated_file_is_read_from_start() {
+        let path = temp_path("truncate");
+        fs::write(&path, "one\ntwo\n").unwrap();
+        let mut follower = FileFollower::new(&path);
+        follower.poll().unwrap();
+
+        fs::write(&path, "new\n").unwrap();
+        assert_eq!(
+            vec![("new".to_string(), 1)],
+            texts(follower.poll().unwrap())
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn invalid_utf8_on_stdin_does_not_stop_reading() {
+        let buffer = Mutex::new(Vec::new());
+        read_lines(&b"one\n\xff\xfe\r\nthree"[..], &buffer);
+        assert_eq!(
+            vec![b"one".to_vec(), b"\xff\xfe".to_vec(), b"three".to_vec()],
+            *buffer.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn rotated_file_is_reopened() {
+        let path = temp_path("rotate");
+        let rotated = temp_path("rotate.1");
+        fs::write(&path, "one\n").unwrap();
+        let mut follower = FileFollower::new(&path);
+        follower.poll().unwrap();
+
+        append(&path, "two\n");
+        fs::rename(&path, &rotated).unwrap();
+        fs::write(&path, "fresh\n").unwrap();
+        assert_eq!(
+            vec![("two".to_string(), 2), ("fresh".to_string(), 1)],
+            texts(follower.poll().unwrap())
+        );
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&rotated).unwrap();
+    }
+}
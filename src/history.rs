@@ -0,0 +1,254 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use crate::dirs::data_dir;
+use crate::pattern::Flags;
+
+/// A pattern that was confirmed with Enter, and what it was used on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Pattern as typed, with its snippets unexpanded
+    pub pattern: String,
+    /// Flags toggled in the ui, kept apart from the pattern
+    #[serde(default)]
+    pub flags: Flags,
+    pub used_at: DateTime<Local>,
+    /// Files, globs or stdin that the pattern was used on
+    pub files: Vec<String>,
+}
+
+impl Entry {
+    pub fn new(pattern: &str, flags: Flags, files: Vec<String>) -> Self {
+        Entry {
+            pattern: pattern.to_string(),
+            flags,
+            used_at: Local::now(),
+            files,
+        }
+    }
+}
+
+/// Patterns of earlier sessions, oldest first, kept one JSON object per line in
+/// `$XDG_DATA_HOME/ire/history.ndjson`. Walking through them with Up and Down keeps
+/// the text that was typed before, like a shell does
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<Entry>,
+    /// File the entries are appended to, none when there is no place to keep it
+    path: Option<PathBuf>,
+    /// Entry shown in the input while walking through the history
+    recalled: Option<usize>,
+    /// Text of the input before walking through the history
+    draft: String,
+    /// Flags before walking through the history
+    draft_flags: Flags,
+}
+
+impl History {
+    /// History of the current user. A history that can't be read is left empty rather
+    /// than stopping ire
+    pub fn load() -> Self {
+        History::open(data_dir())
+    }
+
+    fn open(dir: Option<PathBuf>) -> Self {
+        let path = dir.map(|dir| dir.join("history.ndjson"));
+        let entries = match path.as_ref().map(File::open) {
+            Some(Ok(file)) => BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                // skip lines cut short by a crash, or written by another version
+                .filter_map(|line| serde_json::from_str(&line).ok())
+                .collect(),
+            _ => Vec::new(),
+        };
+        History {
+            entries,
+            path,
+            ..History::default()
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Entry> {
+        self.entries.get(index)
+    }
+
+    /// Appends `entry` to the history file
+    pub fn add(&mut self, entry: Entry) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Pattern and flags before the one recalled, or the newest one when `current` is
+    /// what was typed with `flags`. Patterns equal to `current` are skipped so repeats
+    /// take a single step
+    pub fn previous(&mut self, current: &str, flags: Flags) -> Option<(&str, Flags)> {
+        let before = match self.recalled {
+            Some(recalled) => recalled,
+            None => self.entries.len(),
+        };
+        let found = (0..before)
+            .rev()
+            .find(|&i| self.entries[i].pattern != current)?;
+        if self.recalled.is_none() {
+            self.draft = current.to_string();
+            self.draft_flags = flags;
+        }
+        self.recalled = Some(found);
+        let entry = &self.entries[found];
+        Some((&entry.pattern, entry.flags))
+    }
+
+    /// Pattern and flags after the one recalled, or what was typed once past the newest
+    pub fn next(&mut self, current: &str) -> Option<(&str, Flags)> {
+        let after = self.recalled? + 1;
+        match (after..self.entries.len()).find(|&i| self.entries[i].pattern != current) {
+            Some(found) => {
+                self.recalled = Some(found);
+                let entry = &self.entries[found];
+                Some((&entry.pattern, entry.flags))
+            }
+            None => {
+                self.recalled = None;
+                Some((&self.draft, self.draft_flags))
+            }
+        }
+    }
+
+    /// Newest entry before `before` whose pattern holds `query`
+    pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+        (0..before.min(self.entries.len()))
+            .rev()
+            .find(|&i| self.entries[i].pattern.contains(query))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Search through the history started with Ctrl-R, showing the newest pattern that
+/// holds what is typed
+#[derive(Debug, Default)]
+pub struct Search {
+    pub query: String,
+    /// Entry shown in the input
+    pub found: Option<usize>,
+    /// Text of the input before searching, put back when the search is cancelled
+    pub original: String,
+    /// Flags before searching, put back along with `original`
+    pub original_flags: Flags,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn history(patterns: &[&str]) -> History {
+        History {
+            entries: patterns
+                .iter()
+                .map(|pattern| Entry::new(pattern, Flags::default(), vec!["app.log".to_string()]))
+                .collect(),
+            ..History::default()
+        }
+    }
+
+    fn pattern(recalled: Option<(&str, Flags)>) -> Option<&str> {
+        recalled.map(|(pattern, _)| pattern)
+    }
+
+    #[test]
+    fn up_and_down_walk_through_history_and_back_to_draft() {
+        let mut history = history(&["one", "two"]);
+        assert_eq!(
+            Some("two"),
+            pattern(history.previous("typed", Flags::default()))
+        );
+        assert_eq!(
+            Some("one"),
+            pattern(history.previous("two", Flags::default()))
+        );
+        assert_eq!(None, pattern(history.previous("one", Flags::default())));
+        assert_eq!(Some("two"), pattern(history.next("one")));
+        assert_eq!(Some("typed"), pattern(history.next("two")));
+        assert_eq!(None, pattern(history.next("typed")));
+    }
+
+    #[test]
+    fn entries_written_before_flags_were_kept_are_read() {
+        let line = r#"{"pattern":"a+","used_at":"2021-03-04T05:06:07+01:00","files":[]}"#;
+        let entry: Entry = serde_json::from_str(line).unwrap();
+        assert_eq!(Flags::default(), entry.flags);
+    }
+
+    #[test]
+    fn flags_are_recalled_with_pattern_and_draft() {
+        let mut history = History::default();
+        let flags = Flags {
+            case_insensitive: true,
+            ..Flags::default()
+        };
+        history.entries.push(Entry::new("one", flags, Vec::new()));
+        assert_eq!(
+            Some(("one", flags)),
+            history.previous("typed", Flags::default())
+        );
+        assert_eq!(Some(("typed", Flags::default())), history.next("one"));
+    }
+
+    #[test]
+    fn repeated_patterns_take_a_single_step() {
+        let mut history = history(&["one", "two", "two"]);
+        assert_eq!(Some("two"), pattern(history.previous("", Flags::default())));
+        assert_eq!(
+            Some("one"),
+            pattern(history.previous("two", Flags::default()))
+        );
+        assert_eq!(Some("two"), pattern(history.next("one")));
+    }
+
+    #[test]
+    fn search_finds_newest_match_before() {
+        let history = history(&["error (\\d+)", "warn", "error"]);
+        assert_eq!(Some(2), history.search("err", history.len()));
+        assert_eq!(Some(0), history.search("err", 2));
+        assert_eq!(None, history.search("err", 0));
+        assert_eq!(None, history.search("info", history.len()));
+    }
+
+    #[test]
+    fn added_entries_are_read_back() {
+        let dir = env::temp_dir().join(format!("ire-history-{}", std::process::id()));
+        let mut history = History::open(Some(dir.clone()));
+        assert_eq!(0, history.len());
+        let flags = Flags {
+            case_insensitive: true,
+            ..Flags::default()
+        };
+        let entry = Entry::new("a{{date}}", flags, vec!["app.log".to_string()]);
+        history.add(entry.clone()).unwrap();
+        history
+            .add(Entry::new("b+", Flags::default(), Vec::new()))
+            .unwrap();
+
+        let history = History::open(Some(dir.clone()));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(2, history.len());
+        assert_eq!(Some(&entry), history.get(0));
+    }
+}
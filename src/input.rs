@@ -1,85 +1,329 @@
-use std::cmp::{max, min};
+use std::{
+    cmp::{max, min},
+    mem,
+};
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Kind of edit, consecutive edits of the same kind are undone in a single step
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edit {
+    Typing,
+    Deleting,
+    /// Killing text into the kill ring, where kills in a row are joined
+    Killing,
+    /// An edit that is always a step of its own, e.g. a recalled pattern
+    Other,
+}
 
 pub struct Input {
     pub text: String,
     pub mode: InputMode,
     idx: usize,
+    /// Text and cursor before each step that can be undone, oldest first
+    undo: Vec<(String, usize)>,
+    /// Text and cursor of the steps undone, most recently undone last
+    redo: Vec<(String, usize)>,
+    /// Kind of the last edit, `None` once the cursor moved
+    last_edit: Option<Edit>,
+    /// Text that was killed, most recent last
+    kills: Vec<String>,
+    /// Kill that was yanked right before the cursor, until the next edit or move
+    yanked: Option<usize>,
 }
 
+/// Number of kills that can be yanked back, as in readline
+const KILL_RING_SIZE: usize = 10;
+
 impl Input {
-    pub fn idx(&self) -> &usize {
-        &self.idx
+    /// Replaces the text, leaving the cursor at the end
+    pub fn set(&mut self, text: String) {
+        if text != self.text {
+            self.checkpoint(Edit::Other);
+            self.text = text;
+        }
+        self.idx = self.text.len();
+        self.moved();
+    }
+
+    /// Inserts `text` at the cursor, as a step of its own
+    pub fn insert(&mut self, text: &str) {
+        self.checkpoint(Edit::Other);
+        self.text.insert_str(self.idx, text);
+        self.idx += text.len();
+        self.moved();
+    }
+
+    /// Remembers the text before an edit of `kind`, unless the edit carries on the
+    /// step of the edit before
+    fn checkpoint(&mut self, kind: Edit) {
+        if kind == Edit::Other || self.last_edit != Some(kind) {
+            self.undo.push((self.text.clone(), self.idx));
+        }
+        self.redo.clear();
+        self.last_edit = Some(kind);
+        self.yanked = None;
+    }
+
+    fn moved(&mut self) {
+        self.last_edit = None;
+        self.yanked = None;
+    }
+
+    /// Removes the text between the cursor and `to` into the kill ring. Kills in a
+    /// row make up a single kill, so they are yanked back together
+    fn kill(&mut self, to: usize) {
+        let range = min(self.idx, to)..max(self.idx, to);
+        if range.is_empty() {
+            return;
+        }
+        let joined = self.last_edit == Some(Edit::Killing);
+        self.checkpoint(Edit::Killing);
+        let killed: String = self.text.drain(range.clone()).collect();
+        match self.kills.last_mut() {
+            Some(last) if joined && to < self.idx => last.insert_str(0, &killed),
+            Some(last) if joined => last.push_str(&killed),
+            _ => {
+                if self.kills.len() == KILL_RING_SIZE {
+                    self.kills.remove(0);
+                }
+                self.kills.push(killed);
+            }
+        }
+        self.idx = range.start;
+    }
+
+    /// Column of the cursor on screen, where wide characters such as CJK take two
+    /// columns and combining marks none
+    pub fn cursor(&self) -> usize {
+        self.text[..self.idx].width()
+    }
+
+    /// Start of the grapheme before the cursor, what is seen as a single character
+    fn previous_grapheme(&self) -> usize {
+        self.text[..self.idx]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    /// End of the grapheme after the cursor
+    fn next_grapheme(&self) -> usize {
+        self.text[self.idx..]
+            .graphemes(true)
+            .next()
+            .map_or(self.idx, |grapheme| self.idx + grapheme.len())
+    }
+
+    /// Start of the word before the cursor, where `is_word` tells the graphemes a
+    /// word is made of
+    fn word_start(&self, is_word: fn(&str) -> bool) -> usize {
+        self.text[..self.idx]
+            .grapheme_indices(true)
+            .rev()
+            .skip_while(|(_, grapheme)| !is_word(grapheme))
+            .take_while(|(_, grapheme)| is_word(grapheme))
+            .last()
+            .map_or(0, |(i, _)| i)
+    }
+
+    /// End of the word after the cursor
+    fn word_end(&self, is_word: fn(&str) -> bool) -> usize {
+        self.text[self.idx..]
+            .grapheme_indices(true)
+            .skip_while(|(_, grapheme)| !is_word(grapheme))
+            .take_while(|(_, grapheme)| is_word(grapheme))
+            .last()
+            .map_or(self.text.len(), |(i, grapheme)| {
+                self.idx + i + grapheme.len()
+            })
     }
 }
 
+/// Letters, digits and `_` make up a word for Alt-B, Alt-F and Alt-D, as in readline
+fn is_alphanumeric(grapheme: &str) -> bool {
+    grapheme
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// Anything but whitespace makes up a word for Ctrl-W, so it takes a whole regex token
+fn is_not_whitespace(grapheme: &str) -> bool {
+    !grapheme.chars().all(char::is_whitespace)
+}
+
 impl Default for Input {
     fn default() -> Input {
         Input {
             text: "".to_string(),
             mode: InputMode::Normal,
             idx: 0,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            last_edit: None,
+            kills: Vec::new(),
+            yanked: None,
         }
     }
 }
 
 impl Editable for Input {
     fn left(&mut self) {
-        match &self.idx {
-            0 => {}
-            1..=1000 => self.idx -= 1,
-            _ => {}
-        }
-        ()
+        self.moved();
+        self.idx = self.previous_grapheme();
     }
 
     fn right(&mut self) {
-        self.idx = min(self.text.len(), self.idx + 1)
+        self.moved();
+        self.idx = self.next_grapheme();
     }
 
     fn delete(&mut self) {
         match &self.idx {
             0 => {}
             _ => {
-                self.idx -= 1;
-                self.text.remove(self.idx);
+                self.checkpoint(Edit::Deleting);
+                let start = self.previous_grapheme();
+                self.text.replace_range(start..self.idx, "");
+                self.idx = start;
             }
         }
     }
 
     fn enter(&mut self) {
-        todo!()
+        self.mode = InputMode::Normal;
     }
 
     fn esc(&mut self) {
-        todo!()
+        self.mode = InputMode::Normal;
     }
 
     fn add(&mut self, c: char) {
+        if c.is_whitespace() {
+            // a new word is a step of its own
+            self.moved();
+        }
+        self.checkpoint(Edit::Typing);
         self.text.insert(self.idx, c);
-        self.idx += 1;
+        self.idx += c.len_utf8();
     }
 
     fn home(&mut self) {
+        self.moved();
         self.idx = 0;
     }
 
     fn end(&mut self) {
+        self.moved();
         self.idx = self.text.len();
     }
 
+    fn delete_next(&mut self) {
+        let end = self.next_grapheme();
+        if end > self.idx {
+            self.checkpoint(Edit::Deleting);
+            self.text.replace_range(self.idx..end, "");
+        }
+    }
+
+    fn previous_word(&mut self) {
+        self.moved();
+        self.idx = self.word_start(is_alphanumeric);
+    }
+
+    fn next_word(&mut self) {
+        self.moved();
+        self.idx = self.word_end(is_alphanumeric);
+    }
+
+    fn kill_previous_word(&mut self) {
+        self.kill(self.word_start(is_not_whitespace));
+    }
+
+    fn kill_next_word(&mut self) {
+        self.kill(self.word_end(is_alphanumeric));
+    }
+
+    fn kill_to_start(&mut self) {
+        self.kill(0);
+    }
+
+    fn kill_to_end(&mut self) {
+        self.kill(self.text.len());
+    }
+
+    fn yank(&mut self) {
+        if let Some(killed) = self.kills.last().cloned() {
+            self.insert(&killed);
+            self.yanked = Some(self.kills.len() - 1);
+        }
+    }
+
+    fn yank_pop(&mut self) {
+        if let Some(yanked) = self.yanked {
+            let start = self.idx - self.kills[yanked].len();
+            let older = (yanked + self.kills.len() - 1) % self.kills.len();
+            self.text.replace_range(start..self.idx, &self.kills[older]);
+            self.idx = start + self.kills[older].len();
+            self.yanked = Some(older);
+        }
+    }
+
+    fn transpose(&mut self) {
+        let idx = self.idx;
+        if self.idx == self.text.len() {
+            // at the end of the text, the last two are swapped
+            self.idx = self.previous_grapheme();
+        }
+        let (start, end) = (self.previous_grapheme(), self.next_grapheme());
+        if start == self.idx || end == self.idx {
+            self.idx = idx;
+            return;
+        }
+        self.checkpoint(Edit::Other);
+        let swapped = format!(
+            "{}{}",
+            &self.text[self.idx..end],
+            &self.text[start..self.idx]
+        );
+        self.text.replace_range(start..end, &swapped);
+        self.idx = end;
+    }
+
+    fn undo(&mut self) {
+        if let Some((text, idx)) = self.undo.pop() {
+            self.redo
+                .push((mem::replace(&mut self.text, text), self.idx));
+            self.idx = idx;
+        }
+        self.moved();
+    }
+
+    fn redo(&mut self) {
+        if let Some((text, idx)) = self.redo.pop() {
+            self.undo
+                .push((mem::replace(&mut self.text, text), self.idx));
+            self.idx = idx;
+        }
+        self.moved();
+    }
+
     fn next_boundary(&mut self) {
-        let starting_point = min(self.text.len(), self.idx + 1);
+        self.moved();
+        let starting_point = self.next_grapheme();
 
         let substr = &self.text[starting_point..];
         if let Some(boundary_idx) = substr.find(' ') {
-            // we ignore one position earlier, need to add it back with + 1
-            self.idx += boundary_idx + 1;
+            self.idx = starting_point + boundary_idx;
         } else {
             self.idx = self.text.len();
         }
     }
 
     fn previous_boundary(&mut self) {
+        self.moved();
         match self.idx {
             0 => {}
             _ => {
@@ -106,10 +350,30 @@ pub trait Editable {
     fn add(&mut self, c: char);
     fn home(&mut self);
     fn end(&mut self);
+    /// Takes back the last step of edits
+    fn undo(&mut self);
+    /// Makes the last step undone again, until the text is edited
+    fn redo(&mut self);
     fn next_boundary(&mut self);
     fn previous_boundary(&mut self);
+    /// Deletes the character under the cursor
+    fn delete_next(&mut self);
+    fn previous_word(&mut self);
+    fn next_word(&mut self);
+    /// Kills up to the whitespace before the cursor, like Ctrl-W in a shell
+    fn kill_previous_word(&mut self);
+    fn kill_next_word(&mut self);
+    fn kill_to_start(&mut self);
+    fn kill_to_end(&mut self);
+    /// Inserts the latest kill at the cursor
+    fn yank(&mut self);
+    /// Replaces the text just yanked with the kill before it
+    fn yank_pop(&mut self);
+    /// Swaps the characters around the cursor, moving past them
+    fn transpose(&mut self);
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum InputMode {
     Normal,
     Editing,
@@ -126,7 +390,7 @@ mod tests {
                 fn $func_name() {
                     // Given
                     let (text, idx, expected_idx) = $value;
-                    let mut input = Input {text : text.to_string(), mode : InputMode::Normal, idx};
+                    let mut input = Input {text : text.to_string(), mode : InputMode::Normal, idx, ..Input::default()};
                     // When
                     input.$input_func();
                     // Then
@@ -152,6 +416,19 @@ mod tests {
         previous_boundary: given_already_at_start_then_remain : ("hello world", 0, 0),
         previous_boundary: given_current_idx_is_boundary_then_choose_previous_boundary_match : ("hello world", 5, 0),
         previous_boundary: never_go_negative : ("hello world", 5, 0),
+        left : moves_back_over_multibyte_char : ("café", 5, 3),
+        left : moves_over_combining_mark_with_its_letter : ("cafe\u{301}", 6, 3),
+        right : moves_over_multibyte_char : ("→x", 0, 3),
+        right : moves_over_emoji_sequence : ("👍🏽x", 0, 8),
+        next_boundary: moves_past_multibyte_char : ("é ab", 0, 2),
+        previous_boundary: moves_back_past_multibyte_char : ("a é", 4, 1),
+        previous_word: moves_to_start_of_word : ("foo bar", 6, 4),
+        previous_word: skips_punctuation_before_word : (r"\d+ (\w+)", 9, 6),
+        previous_word: without_word_moves_to_start : ("(.*)", 3, 0),
+        next_word: moves_to_end_of_word : ("foo bar", 1, 3),
+        next_word: skips_punctuation_after_cursor : (r"\d+ (\w+)", 3, 7),
+        next_word: without_word_moves_to_end : ("a(.*)", 1, 5),
+        next_word: keeps_combining_mark_in_word : ("cafe\u{301} x", 0, 6),
     }
 
     macro_rules! test_edit {
@@ -161,7 +438,7 @@ mod tests {
                 fn $func_name() {
                     // Given
                     let (text, idx, c, expected_idx, expected_text) = $value;
-                    let mut input = Input {text : text.to_string(), mode : InputMode::Normal, idx};
+                    let mut input = Input {text : text.to_string(), mode : InputMode::Normal, idx, ..Input::default()};
                     // When
                     input.$input_func(c);
                     // Then
@@ -175,6 +452,124 @@ mod tests {
     test_edit! {
         add : when_add_char_then_increment_idx_by_1 : ("bolloc", 6, 'k', 7, "bollock"),
         add : char_is_inserted_at_index : ("ollock", 0, 'b', 1, "bollock"),
+        add : multibyte_char_moves_idx_past_it : ("ab", 1, 'é', 3, "aéb"),
+    }
+
+    #[test]
+    fn delete_removes_whole_grapheme() {
+        let mut input = Input {
+            text: "xe\u{301}".to_string(),
+            mode: InputMode::Normal,
+            idx: 4,
+            ..Input::default()
+        };
+        input.delete();
+        assert_eq!("x", &input.text);
+        assert_eq!(1, input.idx);
+    }
+
+    macro_rules! test_cursor {
+        ($($func_name:ident: $value:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    // Given
+                    let (text, moves_left, expected_cursor): (&str, usize, usize) = $value;
+                    let mut input = Input::default();
+                    text.chars().for_each(|c| input.add(c));
+                    // When
+                    (0..moves_left).for_each(|_| input.left());
+                    // Then
+                    assert_eq!(expected_cursor, input.cursor());
+                }
+            )*
+        };
+    }
+
+    test_cursor! {
+        ascii_cursor_is_at_idx : ("abc", 1, 2),
+        wide_chars_take_two_columns : ("日本語", 1, 4),
+        mixed_width_text : ("a→日b", 1, 4),
+        combining_mark_takes_no_column : ("e\u{301}x", 0, 2),
+        cursor_before_combining_sequence : ("e\u{301}x", 2, 0),
+    }
+
+    macro_rules! test_undo {
+        ($($func_name:ident: $value:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    // Given
+                    let (edits, expected_text, expected_idx): (&dyn Fn(&mut Input), &str, usize) = $value;
+                    let mut input = Input::default();
+                    // When
+                    edits(&mut input);
+                    // Then
+                    assert_eq!(expected_text, input.text);
+                    assert_eq!(expected_idx, input.idx);
+                }
+            )*
+        };
+    }
+
+    fn type_text(input: &mut Input, text: &str) {
+        text.chars().for_each(|c| input.add(c));
+    }
+
+    test_undo! {
+        undo_takes_back_consecutive_typing : (&|i| { type_text(i, "ab"); i.undo() }, "", 0),
+        undo_takes_back_one_word_at_a_time : (&|i| { type_text(i, "ab cd"); i.undo() }, "ab", 2),
+        moving_the_cursor_ends_a_step : (&|i| { type_text(i, "ab"); i.left(); type_text(i, "x"); i.undo() }, "ab", 1),
+        consecutive_deletes_are_one_step : (&|i| { type_text(i, "abc"); i.left(); i.right(); i.delete(); i.delete(); i.undo() }, "abc", 3),
+        delete_after_typing_is_its_own_step : (&|i| { type_text(i, "abc"); i.delete(); i.undo() }, "abc", 3),
+        redo_makes_undone_step_again : (&|i| { type_text(i, "ab cd"); i.undo(); i.undo(); i.redo() }, "ab", 2),
+        editing_after_undo_drops_redo : (&|i| { type_text(i, "ab"); i.undo(); type_text(i, "x"); i.redo() }, "x", 1),
+        undo_with_nothing_to_undo_keeps_text : (&|i| { i.undo(); i.redo() }, "", 0),
+        set_text_is_a_step_of_its_own : (&|i| { type_text(i, "ab"); i.set("xyz".to_string()); i.undo() }, "ab", 2),
+        inserted_text_is_a_step_of_its_own : (&|i| { type_text(i, "ab"); i.home(); i.insert("{{x}}"); i.undo() }, "ab", 0),
+    }
+
+    macro_rules! test_readline {
+        ($($func_name:ident: $value:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    // Given
+                    let (text, idx, edits, expected_text, expected_idx): (&str, usize, &dyn Fn(&mut Input), &str, usize) = $value;
+                    let mut input = Input {text : text.to_string(), mode : InputMode::Editing, idx, ..Input::default()};
+                    // When
+                    edits(&mut input);
+                    // Then
+                    assert_eq!(expected_text, input.text);
+                    assert_eq!(expected_idx, input.idx);
+                }
+            )*
+        };
+    }
+
+    test_readline! {
+        delete_next_removes_char_under_cursor : ("abc", 1, &|i| i.delete_next(), "ac", 1),
+        delete_next_at_end_keeps_text : ("abc", 3, &|i| i.delete_next(), "abc", 3),
+        delete_next_removes_whole_grapheme : ("e\u{301}x", 0, &|i| i.delete_next(), "x", 0),
+        kill_previous_word_stops_at_whitespace : (r"a \d+(x)", 8, &|i| i.kill_previous_word(), "a ", 2),
+        kill_previous_word_skips_whitespace_first : ("ab cd  ", 7, &|i| i.kill_previous_word(), "ab ", 3),
+        kill_next_word_stops_at_punctuation : ("ab cd.ef", 2, &|i| i.kill_next_word(), "ab.ef", 2),
+        kill_to_start_keeps_rest : ("abcd", 2, &|i| i.kill_to_start(), "cd", 0),
+        kill_to_end_keeps_start : ("abcd", 2, &|i| i.kill_to_end(), "ab", 2),
+        yank_inserts_last_kill : ("abcd", 2, &|i| { i.kill_to_end(); i.home(); i.yank() }, "cdab", 2),
+        yank_with_nothing_killed_keeps_text : ("ab", 1, &|i| i.yank(), "ab", 1),
+        kills_in_a_row_are_yanked_together : ("ab cd ef", 8, &|i| { i.kill_previous_word(); i.kill_previous_word(); i.yank() }, "ab cd ef", 8),
+        kills_after_moving_are_separate : ("ab cd", 5, &|i| { i.kill_previous_word(); i.left(); i.kill_to_start(); i.yank() }, "ab ", 2),
+        forward_kills_in_a_row_are_appended : ("ab cd", 0, &|i| { i.kill_next_word(); i.kill_next_word(); i.yank() }, "ab cd", 5),
+        yank_pop_replaces_yank_with_older_kill : ("ab cd", 5, &|i| { i.kill_previous_word(); i.left(); i.kill_to_start(); i.yank(); i.yank_pop() }, "cd ", 2),
+        yank_pop_wraps_around_kill_ring : ("ab cd", 5, &|i| { i.kill_previous_word(); i.left(); i.kill_to_start(); i.yank(); i.yank_pop(); i.yank_pop() }, "ab ", 2),
+        yank_pop_only_right_after_yank : ("ab cd", 5, &|i| { i.kill_previous_word(); i.yank(); i.left(); i.yank_pop() }, "ab cd", 4),
+        kill_is_undone_in_one_step : ("ab cd", 5, &|i| { i.kill_previous_word(); i.kill_previous_word(); i.undo() }, "ab cd", 5),
+        transpose_swaps_chars_around_cursor : ("abc", 1, &|i| i.transpose(), "bac", 2),
+        transpose_at_end_swaps_last_two : ("abc", 3, &|i| i.transpose(), "acb", 3),
+        transpose_at_start_keeps_text : ("abc", 0, &|i| i.transpose(), "abc", 0),
+        transpose_single_char_keeps_text : ("a", 1, &|i| i.transpose(), "a", 1),
+        transpose_swaps_graphemes : ("→e\u{301}", 3, &|i| i.transpose(), "e\u{301}→", 6),
     }
 
     #[test]
@@ -183,6 +578,7 @@ mod tests {
             text: "bla".to_string(),
             mode: InputMode::Normal,
             idx: 0,
+            ..Input::default()
         };
         input.delete();
         assert_eq!("bla", &input.text);
@@ -194,9 +590,10 @@ mod tests {
             text: "bla".to_string(),
             mode: InputMode::Normal,
             idx: 2,
+            ..Input::default()
         };
         input.delete();
-        assert_eq!(1, *input.idx());
+        assert_eq!(1, input.idx);
         assert_eq!("ba", &input.text);
     }
 }
@@ -0,0 +1,267 @@
+use std::{collections::BTreeMap, fs, ops::Range, path::Path};
+
+use regex::bytes::Regex;
+
+use crate::dirs::config_dir;
+use crate::error::IreError;
+use crate::pattern::{compile, Flags, PatternError};
+
+/// Snippets that are always there, unless the library gives another pattern by the
+/// same name
+const BUILT_IN: [(&str, &str); 7] = [
+    ("date", r"\d{4}-\d{2}-\d{2}"),
+    (
+        "timestamp",
+        r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:[.,]\d+)?(?:Z|[+-]\d{2}:?\d{2})?",
+    ),
+    (
+        "uuid",
+        r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}",
+    ),
+    (
+        "ipv4",
+        r"(?:(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)\.){3}(?:25[0-5]|2[0-4]\d|1\d\d|[1-9]?\d)",
+    ),
+    (
+        "ipv6",
+        r"(?:[0-9a-fA-F]{1,4}:){1,7}(?::[0-9a-fA-F]{1,4}){1,7}|(?:[0-9a-fA-F]{1,4}:){7}[0-9a-fA-F]{1,4}",
+    ),
+    (
+        "level",
+        r"(?i:trace|debug|info|warn(?:ing)?|error|fatal|critical)",
+    ),
+    ("hex", r"(?:0x)?[0-9a-fA-F]+"),
+];
+
+/// Named snippets of patterns, shared by a team so that conventions such as the
+/// format of a timestamp live in one place. A snippet is used in a pattern as
+/// `{{name}}`, and is read from a JSON object of names and patterns such as
+/// `{"request": "req-\\d+"}`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Library {
+    snippets: BTreeMap<String, String>,
+}
+
+impl Default for Library {
+    fn default() -> Self {
+        Library {
+            snippets: BUILT_IN
+                .iter()
+                .map(|(name, pattern)| (name.to_string(), pattern.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl Library {
+    /// The library at `path`, or at `$XDG_CONFIG_HOME/ire/library.json` when it exists
+    pub fn load(path: Option<&str>) -> Result<Self, IreError> {
+        let path = match path {
+            Some(path) => Path::new(path).to_path_buf(),
+            None => match config_dir().map(|dir| dir.join("library.json")) {
+                Some(path) if path.exists() => path,
+                _ => return Ok(Library::default()),
+            },
+        };
+        let read = |path: &Path| -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+            Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+        };
+        let snippets = read(&path).map_err(|source| IreError::Library {
+            path: path.display().to_string(),
+            source,
+        })?;
+        let mut library = Library::default();
+        library.snippets.extend(snippets);
+        Ok(library)
+    }
+
+    /// Names and patterns of every snippet, by name
+    pub fn snippets(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.snippets
+            .iter()
+            .map(|(name, pattern)| (name.as_str(), pattern.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.snippets.len()
+    }
+
+    /// `pattern` with every `{{name}}` replaced by its snippet, as a group of its own
+    /// so a repetition after it applies to the whole snippet
+    fn expand(&self, pattern: &str) -> Result<Expansion, PatternError> {
+        let mut expansion = Expansion::default();
+        let mut rest = 0;
+        while let Some((name, span)) = next_macro(pattern, rest) {
+            let snippet = self.snippets.get(name).ok_or_else(|| PatternError {
+                message: format!("no snippet named {}", name),
+                span: Some(span.clone()),
+            })?;
+            expansion.text.push_str(&pattern[rest..span.start]);
+            let start = expansion.text.len();
+            expansion.text.push_str(&format!("(?:{})", snippet));
+            expansion
+                .macros
+                .push((span.clone(), start..expansion.text.len()));
+            rest = span.end;
+        }
+        expansion.text.push_str(&pattern[rest..]);
+        Ok(expansion)
+    }
+
+    /// Compiles `pattern` with its snippets expanded, see `pattern::compile`. The
+    /// error points at the part of `pattern` as written, a whole snippet when the
+    /// problem is inside of it
+    pub fn compile(&self, pattern: &str, flags: Flags) -> Result<Regex, PatternError> {
+        let expansion = self.expand(pattern)?;
+        compile(&expansion.text, flags).map_err(|err| PatternError {
+            span: err.span.map(|span| expansion.original(span)),
+            ..err
+        })
+    }
+}
+
+/// A pattern with its snippets expanded
+#[derive(Debug, Default)]
+struct Expansion {
+    text: String,
+    /// Span of every `{{name}}` in the pattern, and of its snippet in `text`
+    macros: Vec<(Range<usize>, Range<usize>)>,
+}
+
+impl Expansion {
+    /// Span in the pattern of `span` in the expanded text
+    fn original(&self, span: Range<usize>) -> Range<usize> {
+        let start = self.position(span.start, |written| written.start);
+        let end = self
+            .position(span.end, |written| written.end)
+            .max(start + 1);
+        start..end
+    }
+
+    /// Position in the pattern of `offset` in the expanded text, or the `side` of the
+    /// macro that `offset` falls inside of
+    fn position(&self, offset: usize, side: fn(&Range<usize>) -> usize) -> usize {
+        let mut shift: isize = 0;
+        for (written, expanded) in &self.macros {
+            if offset < expanded.start {
+                break;
+            }
+            if offset < expanded.end {
+                return side(written);
+            }
+            shift += expanded.len() as isize - written.len() as isize;
+        }
+        (offset as isize - shift) as usize
+    }
+}
+
+/// Name and span of the first `{{name}}` in `pattern` from `from`, where a name is
+/// made of letters, digits, `_` and `-`
+fn next_macro(pattern: &str, from: usize) -> Option<(&str, Range<usize>)> {
+    let mut search = from;
+    while let Some(open) = pattern[search..].find("{{").map(|i| search + i) {
+        let name_start = open + 2;
+        let name_len = pattern[name_start..]
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(pattern.len() - name_start);
+        let name_end = name_start + name_len;
+        if name_len > 0 && pattern[name_end..].starts_with("}}") {
+            return Some((&pattern[name_start..name_end], open..name_end + 2));
+        }
+        search = open + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library(snippets: &[(&str, &str)]) -> Library {
+        Library {
+            snippets: snippets
+                .iter()
+                .map(|(name, pattern)| (name.to_string(), pattern.to_string()))
+                .collect(),
+        }
+    }
+
+    macro_rules! test_expand {
+        ($($func_name:ident: $values:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    let (pattern, expected): (&str, &str) = $values;
+                    let library = library(&[("id", r"\d+"), ("user-name", r"\w+")]);
+                    assert_eq!(expected, library.expand(pattern).unwrap().text);
+                }
+            )*
+        }
+    }
+
+    test_expand! {
+        without_macros_pattern_is_kept : (r"a{2}b", r"a{2}b"),
+        macro_is_replaced_by_group : ("id={{id}}", r"id=(?:\d+)"),
+        every_macro_is_replaced : ("{{user-name}}:{{id}}", r"(?:\w+):(?:\d+)"),
+        repetition_applies_to_whole_snippet : ("{{id}}+", r"(?:\d+)+"),
+        braces_without_name_are_kept : ("{{}} {{ id}}", "{{}} {{ id}}"),
+        open_braces_before_macro : ("{{{{id}}", r"{{(?:\d+)"),
+    }
+
+    #[test]
+    fn unknown_snippet_points_at_macro() {
+        let err = library(&[])
+            .compile("a {{nope}}", Flags::default())
+            .unwrap_err();
+        assert_eq!("no snippet named nope", err.message);
+        assert_eq!(Some(2..10), err.span);
+    }
+
+    #[test]
+    fn error_after_macro_points_into_pattern_as_written() {
+        let err = library(&[("id", r"\d+")])
+            .compile("{{id}} (", Flags::default())
+            .unwrap_err();
+        assert_eq!(Some(7..8), err.span);
+    }
+
+    #[test]
+    fn error_inside_snippet_points_at_macro() {
+        let err = library(&[("bad", r"\q")])
+            .compile("a{{bad}}", Flags::default())
+            .unwrap_err();
+        assert_eq!(Some(1..8), err.span);
+    }
+
+    #[test]
+    fn built_in_snippets_match_what_they_are_named_after() {
+        let library = Library::default();
+        let matches = |pattern: &str, text: &str| {
+            library
+                .compile(&format!("^{}$", pattern), Flags::default())
+                .unwrap()
+                .is_match(text.as_bytes())
+        };
+        assert!(matches("{{timestamp}}", "2021-03-04T05:06:07.123+01:00"));
+        assert!(matches("{{uuid}}", "123e4567-e89b-12d3-a456-426614174000"));
+        assert!(matches("{{ipv4}}", "192.168.0.255"));
+        assert!(!matches("{{ipv4}}", "192.168.0.256"));
+        assert!(matches("{{ipv6}}", "fe80::1ff:fe23:4567:890a"));
+        assert!(matches("{{level}}", "WARN"));
+    }
+
+    #[test]
+    fn library_file_adds_to_built_in_snippets() {
+        let path = std::env::temp_dir().join(format!("ire-library-{}.json", std::process::id()));
+        fs::write(&path, r#"{"request": "req-\\d+", "date": "\\d{8}"}"#).unwrap();
+        let library = Library::load(path.to_str());
+        fs::remove_file(&path).unwrap();
+
+        let library = library.unwrap();
+        assert_eq!(BUILT_IN.len() + 1, library.len());
+        let re = library
+            .compile("{{request}} {{date}}", Flags::default())
+            .unwrap();
+        assert!(re.is_match(b"req-1 20210304"));
+    }
+}
@@ -1,250 +1,1189 @@
 mod capture;
 /// Simple tui that interactively shows matching lines in input
 mod color;
+mod context;
 mod crate_tests;
+mod dirs;
+mod encoding;
+mod error;
 mod event;
+mod follow;
+mod history;
 mod input;
+mod library;
+mod output;
+mod pattern;
+mod records;
+mod scroll;
+mod search;
+mod source;
+mod store;
+mod terminal;
 
-use crate::capture::{filter_matches, into_matchsets, MatchSet};
-use crate::color::Styled;
+use crate::capture::{MatchSet, RecordLayout};
+use crate::color::{gutter_style, legend, split_rows, style_replaced, Styled};
+use crate::context::{context_rows, Context, ContextLines, Row};
+use crate::encoding::Encoding;
+use crate::error::{IreError, EXIT_NO_MATCH};
 use crate::event::{Event, Events};
+use crate::follow::{followers_of, Follow};
+use crate::history::{Entry, History, Search};
 use crate::input::{Editable, Input};
-use clap::clap_app;
+use crate::library::Library;
+use crate::output::{Format, MatchWriter, CONTEXT_BREAK};
+use crate::pattern::{Flag, Flags, PatternError};
+use crate::records::{Grouping, Records};
+use crate::scroll::Scroll;
+use crate::search::{MatchIndex, Query, Searcher};
+use crate::source::{is_stdin, unescape, Delimiter, Line, Source};
+use crate::store::{LineStore, SharedStore};
+use crate::terminal::Backend;
+use clap::{clap_app, value_t, ArgMatches};
 use colored::Colorize;
-use csv::Writer;
-use glob::glob;
-use regex::Regex;
+use regex::bytes::Regex;
 use std::io::Write;
-use std::{error::Error, fs, io};
-use termion::{event::Key, input::MouseTerminal, raw::IntoRawMode, screen::AlternateScreen};
+use std::{
+    env,
+    ffi::OsString,
+    fs, io, process,
+    sync::{Arc, RwLock},
+};
+use termion::event::Key;
 use tui::{
-    backend::TermionBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Span, Spans, Text},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
     Terminal,
 };
 
 use input::InputMode;
 
+/// Input field that keys are sent to
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Pattern,
+    Replacement,
+}
+
+/// Flag toggled by `key` in normal mode
+fn flag_of(key: char) -> Option<Flag> {
+    match key {
+        'I' => Some(Flag::CaseInsensitive),
+        'M' => Some(Flag::MultiLine),
+        'S' => Some(Flag::DotMatchesNewLine),
+        'X' => Some(Flag::IgnoreWhitespace),
+        'U' => Some(Flag::Unicode),
+        _ => None,
+    }
+}
+
 /// App holds the state of the application
-struct App<'a> {
+struct App {
     input: Input,
-    // pattern_matches: Vec<String>,
-    pattern_matches: Vec<MatchSet<'a>>,
+    /// Template that matches are replaced with, see `MatchSet::replaced`
+    replacement: Input,
+    focus: Field,
     re: Regex,
+    /// Flags `re` is compiled with
+    flags: Flags,
+    /// Show the lines that don't match instead
+    invert: bool,
+    /// Lines shown around each match
+    context: Context,
+    store: SharedStore,
+    searcher: Searcher,
+    index: MatchIndex,
+    /// Set while the input doesn't compile, `re` is then the last pattern that did
+    error: Option<PatternError>,
+    /// Position in the matched lines
+    scroll: Scroll,
+    /// Number of matched lines that fit on screen when last drawn
+    page: usize,
+    /// Patterns of earlier sessions
+    history: History,
+    /// Set while searching the history with Ctrl-R
+    search: Option<Search>,
+    /// Snippets that can be used in the pattern as `{{name}}`
+    library: Library,
+    /// Snippet chosen in the picker, set while it is open
+    picker: Option<Scroll>,
 }
 
-impl<'a> Default for App<'a> {
-    fn default() -> App<'a> {
+impl App {
+    fn new(store: SharedStore, searcher: Searcher, context: Context) -> App {
         App {
             input: Input::default(),
-            // pattern_matches: Vec::new(),
-            pattern_matches: Vec::new(),
+            replacement: Input::default(),
+            focus: Field::Pattern,
             re: Regex::new("").unwrap(),
+            flags: Flags::default(),
+            invert: false,
+            context,
+            store,
+            searcher,
+            index: MatchIndex::new(),
+            error: None,
+            scroll: Scroll::default(),
+            page: 0,
+            history: History::default(),
+            search: None,
+            library: Library::default(),
+            picker: None,
+        }
+    }
+
+    /// Handles `key` while the snippet picker is open. Enter inserts the snippet
+    /// picked at the cursor, as `{{name}}`
+    fn pick_snippet(&mut self, key: Key) {
+        let picker = match self.picker.as_mut() {
+            Some(picker) => picker,
+            None => return,
+        };
+        match key {
+            Key::Down | Key::Ctrl('n') => picker.down(1, self.library.len()),
+            Key::Up | Key::Ctrl('p') => picker.up(1),
+            Key::Char('\n') => {
+                let selected = picker.selected();
+                self.picker = None;
+                if let Some((name, _)) = self.library.snippets().nth(selected) {
+                    self.input.insert(&format!("{{{{{}}}}}", name));
+                }
+            }
+            Key::Esc => self.picker = None,
+            _ => {}
+        }
+    }
+
+    /// Puts a pattern from the history in the input, along with the flags it was used
+    /// with
+    fn recall(&mut self, recalled: Option<(String, Flags)>) {
+        if let Some((pattern, flags)) = recalled {
+            self.input.set(pattern);
+            if flags != self.flags {
+                self.flags = flags;
+                self.update_pattern(true);
+            }
+        }
+    }
+
+    /// Handles `key` while searching the history, see `Search`. Enter keeps the pattern
+    /// found, Esc puts back what was typed before
+    fn search_history(&mut self, key: Key) {
+        let search = match self.search.as_mut() {
+            Some(search) => search,
+            None => return,
+        };
+        let newest = self.history.len();
+        match key {
+            Key::Ctrl('r') => {
+                let before = search.found.unwrap_or(newest);
+                search.found = self.history.search(&search.query, before).or(search.found);
+            }
+            Key::Char('\n') => {
+                self.search = None;
+                return;
+            }
+            Key::Esc | Key::Ctrl('g') => {
+                let original = std::mem::take(&mut search.original);
+                let flags = search.original_flags;
+                self.search = None;
+                return self.recall(Some((original, flags)));
+            }
+            Key::Backspace => {
+                search.query.pop();
+                search.found = self.history.search(&search.query, newest);
+            }
+            Key::Char(c) => {
+                search.query.push(c);
+                // the pattern shown may still hold the longer query
+                let before = search.found.map_or(newest, |found| found + 1);
+                search.found = self.history.search(&search.query, before);
+            }
+            _ => return,
         }
+        let history = &self.history;
+        let recalled = match search.found.and_then(|found| history.get(found)) {
+            Some(entry) => Some((entry.pattern.clone(), entry.flags)),
+            None if search.query.is_empty() => {
+                Some((search.original.clone(), search.original_flags))
+            }
+            None => None,
+        };
+        self.recall(recalled);
     }
+
+    fn focused(&mut self) -> &mut Input {
+        match self.focus {
+            Field::Pattern => &mut self.input,
+            Field::Replacement => &mut self.replacement,
+        }
+    }
+
+    fn mode(&self) -> InputMode {
+        match self.focus {
+            Field::Pattern => self.input.mode,
+            Field::Replacement => self.replacement.mode,
+        }
+    }
+
+    /// Starts editing `field`, leaving the field edited before
+    fn edit(&mut self, field: Field) {
+        self.focused().mode = InputMode::Normal;
+        self.focus = field;
+        self.focused().mode = InputMode::Editing;
+    }
+
+    /// Replacement template, once one is given
+    fn template(&self) -> Option<&str> {
+        Some(self.replacement.text.as_str()).filter(|text| !text.is_empty())
+    }
+
+    fn query(&self) -> Query {
+        Query {
+            re: self.re.clone(),
+            invert: self.invert,
+        }
+    }
+
+    /// Pattern with its snippets expanded and the flags it was compiled with, as it
+    /// would be written inline
+    fn pattern(&self) -> String {
+        format!("{}{}", self.flags, self.re.as_str())
+    }
+
+    /// Starts over with the lines matching the current query
+    fn search(&mut self) {
+        self.index.reset(self.searcher.search(self.query()));
+        self.scroll.top();
+    }
+
+    /// Searches again if the pattern in the input changed, or always when `force`d
+    /// e.g. after the flags changed. The current result is left in place while the
+    /// pattern doesn't compile
+    fn update_pattern(&mut self, force: bool) {
+        match self.library.compile(&self.input.text, self.flags) {
+            Ok(re) => {
+                self.error = None;
+                if force || re.as_str() != self.re.as_str() {
+                    self.re = re;
+                    self.search();
+                }
+            }
+            Err(err) => self.error = Some(err),
+        }
+    }
+
+    /// The input, with the part of the pattern that doesn't compile highlighted
+    fn input_spans(&self) -> Spans<'_> {
+        let text = self.input.text.as_str();
+        match self.error.as_ref().and_then(|err| err.span.clone()) {
+            Some(span) => {
+                let end = span.end.min(text.len());
+                let error_style = Style::default()
+                    .fg(Color::White)
+                    .bg(Color::Red)
+                    .add_modifier(Modifier::BOLD);
+                let mut spans = vec![
+                    Span::raw(&text[..span.start]),
+                    Span::styled(&text[span.start..end], error_style),
+                    Span::raw(&text[end..]),
+                ];
+                if span.end > text.len() {
+                    // the pattern ended too early, mark where more was expected
+                    spans.push(Span::styled(" ", error_style));
+                }
+                Spans::from(spans)
+            }
+            None => Spans::from(text),
+        }
+    }
+
+    /// Resumes a finished search when lines were added after it was started
+    fn catch_up(&mut self) {
+        if self.index.is_done() && self.index.scanned() < self.store.read().unwrap().len() {
+            self.index.resume();
+            self.searcher.resume(self.query(), self.index.scanned());
+        }
+    }
+}
+
+fn main() {
+    let result = run();
+    if let Err(err) = &result {
+        eprintln!("{}", err);
+    }
+    process::exit(exit_code(&result));
+}
+
+/// Exit code for the result of `run`. Like grep, it tells whether any line matched
+fn exit_code(result: &Result<usize, IreError>) -> i32 {
+    match result {
+        Ok(0) => EXIT_NO_MATCH,
+        Ok(_) => 0,
+        Err(err) => err.exit_code(),
+    }
+}
+
+/// Runs ire, returning the number of matching lines that were written
+fn run() -> Result<usize, IreError> {
+    let matches = arguments(env::args_os())?;
+    let context = context_of(&matches)?;
+
+    let source = source_of(&matches);
+    if let Source::Glob(glob_pattern) = source {
+        glob::Pattern::new(glob_pattern)?;
+    }
+    if source == Source::Stdin && termion::is_tty(&io::stdin()) {
+        eprintln!("{}", matches.usage());
+        return Err(IreError::NoInput);
+    }
+
+    if let Some(pattern) = matches.value_of("PATTERN") {
+        // batch mode, the pattern is already known so the input is streamed straight
+        // to the output
+        let query = Query {
+            re: Library::load(matches.value_of("LIBRARY"))?.compile(pattern, Flags::default())?,
+            invert: matches.is_present("INVERT"),
+        };
+        let replacement = matches.value_of("REPLACE");
+        let records = Records::new(
+            source.records(&delimiter_of(&matches)?, encoding_of(&matches)),
+            grouping_of(&matches)?,
+        );
+        let out = open_output(&matches)?;
+        return write_result(out, &matches, &query, replacement, records, context, None);
+    }
+
+    let mut followers = Vec::new();
+    let store = if matches.is_present("FOLLOW") {
+        followers = followers_of(&source);
+        let mut store = LineStore::new();
+        for follower in followers.iter_mut() {
+            store.push_lines(follower.poll().map_err(IreError::read)?);
+        }
+        store
+    } else {
+        let encoding = encoding_of(&matches);
+        match (delimiter_of(&matches)?, grouping_of(&matches)?) {
+            (Delimiter::Newline, Grouping::Lines) if encoding == Encoding::Utf8 => {
+                LineStore::from_source(&source).map_err(IreError::read)?
+            }
+            // records are decoded, split or joined while reading, so they are kept in memory
+            (delimiter, grouping) => {
+                let mut store = LineStore::new();
+                let records = Records::new(source.records(&delimiter, encoding), grouping);
+                store.push_lines(records.collect::<io::Result<_>>().map_err(IreError::read)?);
+                store
+            }
+        }
+    };
+
+    // matches execute when exiting the program
+    let mut app = run_interactive(store, followers, &matches, context)?;
+    let matched = write_result(
+        open_output(&matches)?,
+        &matches,
+        &app.query(),
+        app.template(),
+        app.store.read().unwrap().iter(),
+        context,
+        Some(&app.pattern()),
+    )?;
+    // only patterns whose result was written are worth recalling
+    if let Err(err) = app
+        .history
+        .add(Entry::new(&app.input.text, app.flags, source.names()))
+    {
+        eprintln!("Unable to save pattern history: {}", err);
+    }
+    Ok(matched)
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let matches = clap_app!(interactive_regex =>
+/// Options and arguments of the command line. `--help` and `--version` are printed
+/// right away, wrong options are an error so they exit like other errors
+fn arguments<I, T>(args: I) -> Result<ArgMatches<'static>, IreError>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let app = clap_app!(interactive_regex =>
         (version: "1.0")
         (author: "Freddy Järvå <freddy.a.jarva@gmail.com>")
         (about: "Coding Monkey Extraordinaire")
-        (@arg FILENAME: +required conflicts_with[GLOB])
+        (@arg FILENAME: conflicts_with[GLOB] "file to read, stdin is read when omitted or '-'")
+        (@arg FOLLOW: -F --follow conflicts_with[PATTERN MULTILINE RECORD_START DELIMITER RECORD_REGEX ENCODING]
+            "keep reading lines appended to the input, following the file if it is truncated or rotated")
+        (@arg PATTERN: -e --pattern +takes_value
+            "match with this pattern and write the result without starting the interactive ui")
         (@arg GLOB: -g --glob +takes_value "use glob pattern to read from multiple files")
         (@arg OUTPUT: -o --output +takes_value "write result to file")
-    )
-    .get_matches();
+        (@arg FORMAT: -f --format +takes_value possible_value[text csv tsv json ndjson]
+            "write the matched lines, or their captured groups, in this format. Defaults to csv for --output, text otherwise")
+        (@arg LAYOUT: -l --layout +takes_value possible_value[rows joined] default_value("rows")
+            "write one row per match, or one row per line with the matches joined by ';'")
+        (@arg MULTILINE: -m --multiline conflicts_with[RECORD_START]
+            "match the pattern over each whole file instead of line by line, so matches can span lines")
+        (@arg RECORD_START: --("record-start") +takes_value
+            "match the pattern over records of lines, where a record starts at every line matching this regex")
+        (@arg DELIMITER: -d --delimiter +takes_value conflicts_with[RECORD_REGEX]
+            "split the input into records ended by this string instead of lines, where \\0, \\n, \\r and \\t are escapes")
+        (@arg RECORD_REGEX: --("record-regex") +takes_value
+            "split the input into records separated by every match of this regex, e.g. '\\n\\n+' for blank lines")
+        (@arg LIBRARY: --library +takes_value
+            "read snippets used in patterns as {{name}} from this JSON file, instead of $XDG_CONFIG_HOME/ire/library.json")
+        (@arg ENCODING: --encoding +takes_value possible_values(&["utf-8", "latin1", "utf-16", "utf-16le", "utf-16be"])
+            default_value("utf-8") "decode the input from this encoding, utf-16 follows the byte order mark")
+        (@arg INVERT: -v --("invert-match") "show the lines that don't match instead")
+        (@arg AFTER: -A --("after-context") +takes_value "show this many lines after each match")
+        (@arg BEFORE: -B --("before-context") +takes_value "show this many lines before each match")
+        (@arg CONTEXT: -C --context +takes_value "show this many lines before and after each match")
+        (@arg REPLACE: -r --replace +takes_value conflicts_with[FORMAT]
+            "write the matched lines with every match replaced by this template, where $1 or ${name} insert a group")
+    );
+    match app.get_matches_from_safe(args) {
+        Ok(matches) => Ok(matches),
+        Err(err) if !err.use_stderr() => err.exit(),
+        Err(err) => Err(IreError::Args(err)),
+    }
+}
 
-    let contents: Vec<String> = if let Some(glob_pattern) = matches.value_of("GLOB") {
-        let mut strings: Vec<String> = Vec::new();
-        for entry in glob(glob_pattern).unwrap() {
-            let file_content = fs::read_to_string(entry.unwrap()).unwrap();
-            strings.extend(file_content.split('\n').map(|s| s.to_string()));
+/// Where the input is read from, as given by the arguments
+fn source_of<'a>(matches: &'a ArgMatches) -> Source<'a> {
+    if let Some(glob_pattern) = matches.value_of("GLOB") {
+        Source::Glob(glob_pattern)
+    } else {
+        match matches.value_of("FILENAME") {
+            Some(filename) if !is_stdin(filename) => Source::File(filename),
+            _ => Source::Stdin,
         }
-        strings
+    }
+}
+
+/// `--output`, or stdout when it is not given
+fn open_output(matches: &ArgMatches) -> Result<Box<dyn Write>, IreError> {
+    Ok(match matches.value_of("OUTPUT") {
+        Some(output) => {
+            let file =
+                fs::File::create(output).map_err(|err| IreError::write(Some(output), err))?;
+            Box::new(io::BufWriter::new(file))
+        }
+        None => Box::new(io::BufWriter::new(io::stdout())),
+    })
+}
+
+/// Encoding of the input, asked for with `--encoding`
+fn encoding_of(matches: &ArgMatches) -> Encoding {
+    matches
+        .value_of("ENCODING")
+        .and_then(Encoding::from_name)
+        .unwrap_or(Encoding::Utf8)
+}
+
+/// Where the input is split into records, asked for with `--delimiter` or `--record-regex`
+fn delimiter_of(matches: &ArgMatches) -> Result<Delimiter, regex::Error> {
+    Ok(if let Some(delimiter) = matches.value_of("DELIMITER") {
+        Delimiter::Bytes(unescape(delimiter))
+    } else if let Some(re) = matches.value_of("RECORD_REGEX") {
+        Delimiter::Regex(Box::new(regex::bytes::Regex::new(re)?))
     } else {
-        let filename = matches.value_of("FILENAME").unwrap();
-        fs::read_to_string(filename)
-            .expect(&format!("Unable to read file \"{}\"", filename))
-            .split("\n")
-            .map(|s| s.to_string())
-            .collect()
-    };
+        Delimiter::Newline
+    })
+}
 
-    // Terminal initialization
-    let stdout = io::stdout().into_raw_mode()?;
-    let stdout = MouseTerminal::from(stdout);
-    let stdout = AlternateScreen::from(stdout);
-    let backend = TermionBackend::new(stdout);
-    let terminal = Terminal::new(backend)?;
+/// How lines are joined into records, asked for with `--multiline` or `--record-start`
+fn grouping_of(matches: &ArgMatches) -> Result<Grouping, regex::Error> {
+    Ok(if matches.is_present("MULTILINE") {
+        Grouping::Buffer
+    } else if let Some(start) = matches.value_of("RECORD_START") {
+        Grouping::Start(Box::new(Regex::new(start)?))
+    } else {
+        Grouping::Lines
+    })
+}
 
-    let events = Events::new();
+/// Lines of context asked for with `-A`, `-B` and `-C`
+fn context_of(matches: &ArgMatches) -> Result<Context, clap::Error> {
+    let lines = |name| match matches.is_present(name) {
+        true => value_t!(matches, name, usize).map(Some),
+        false => Ok(None),
+    };
+    let both = lines("CONTEXT")?.unwrap_or(0);
+    Ok(Context {
+        before: lines("BEFORE")?.unwrap_or(both),
+        after: lines("AFTER")?.unwrap_or(both),
+    })
+}
 
-    let app = App::default();
+/// Writes the lines found by `query` to `out`, in the `--format` asked for, returning
+/// how many matched. With a `replacement` template the lines are written with their
+/// matches replaced. After the interactive ui, the `pattern` that was crafted is
+/// written last
+fn write_result<W, I>(
+    out: W,
+    matches: &ArgMatches,
+    query: &Query,
+    replacement: Option<&str>,
+    lines: I,
+    context: Context,
+    pattern: Option<&str>,
+) -> Result<usize, IreError>
+where
+    W: Write,
+    I: Iterator<Item = io::Result<Line>>,
+{
+    let output = matches.value_of("OUTPUT");
+    let format = match matches.value_of("FORMAT").and_then(Format::from_arg) {
+        Some(format) => format,
+        None if output.is_some() && replacement.is_none() => Format::Csv,
+        None => Format::Text,
+    };
+    let layout = RecordLayout::from_arg(matches.value_of("LAYOUT").unwrap()).unwrap();
+    let failed = |err| IreError::write(output, err);
 
-    match begin_loop(terminal, app, contents, events) {
-        // matches execute when exiting the program
-        Ok((contents, re)) => {
-            let mats = filter_matches(&contents, &re);
-            let mats = into_matchsets(&mats, &re);
-            if let Some(output) = matches.value_of("OUTPUT") {
-                let mut writer = Writer::from_path(output).unwrap();
-                for line in mats {
-                    writer.write_record(line.to_strings())?;
-                }
-            } else {
-                let stdout = io::stdout();
-                let mut handle = io::BufWriter::new(stdout.lock());
-                for line in mats {
-                    writeln!(handle, "{}", line.raw_line())?;
-                }
-                writeln!(handle, "Lines were matched with: {}", re.as_str().green())?;
+    let re = &query.re;
+    let mut writer = MatchWriter::new(out, format, layout, re);
+    if let Some(template) = replacement {
+        writer = writer.replacing(template);
+    }
+    if output.is_none() && termion::is_tty(&io::stdout()) {
+        writer = writer.dimming();
+    }
+    let mut matched = 0;
+    for row in ContextLines::new(lines, re, query.invert, context) {
+        let written = match row.map_err(IreError::read)? {
+            Row::Match(line) => {
+                matched += 1;
+                writer.write(&MatchSet::from_line(&line, re))
             }
+            Row::Context(line) => writer.write_context(&line.text()),
+            Row::Break => writer.write_break(),
+        };
+        written.map_err(|err| IreError::write(output, err))?;
+    }
+    let mut out = writer
+        .finish()
+        .map_err(|err| IreError::write(output, err))?;
+    match pattern {
+        Some(pattern) if output.is_none() && format == Format::Text => {
+            let summary = match query.invert {
+                true => format!("Lines were not matching: {}", pattern.green()),
+                false => format!("Lines were matched with: {}", pattern.green()),
+            };
+            let replaced = match replacement {
+                Some(template) => format!(" and replaced with: {}", template.green()),
+                None => String::new(),
+            };
+            writeln!(out, "{}{}", summary, replaced).map_err(failed)?;
         }
-        Err(err) => {
-            eprintln!("program crash: {}", err)
-        }
+        _ => {}
     }
+    out.flush().map_err(failed)?;
+    Ok(matched)
+}
 
-    Ok(())
+fn run_interactive(
+    store: LineStore,
+    followers: Vec<Box<dyn Follow>>,
+    matches: &ArgMatches,
+    context: Context,
+) -> Result<App, IreError> {
+    let terminal = terminal::enter()?;
+
+    let events = Events::new();
+    if !followers.is_empty() {
+        events.follow(followers);
+    }
+
+    let store = Arc::new(RwLock::new(store));
+    let searcher = Searcher::new(store.clone(), events.sender());
+    let mut app = App::new(store, searcher, context);
+    app.invert = matches.is_present("INVERT");
+    app.history = History::load();
+    app.library = Library::load(matches.value_of("LIBRARY"))?;
+    app.search();
+    if let Some(template) = matches.value_of("REPLACE") {
+        app.replacement.set(template.to_string());
+    }
+
+    let result = begin_loop(terminal, app, events);
+    terminal::leave();
+    result
 }
 
-fn begin_loop<'a>(
-    mut terminal: Terminal<
-        TermionBackend<AlternateScreen<MouseTerminal<termion::raw::RawTerminal<io::Stdout>>>>,
-    >,
-    mut app: App<'a>,
-    contents: Vec<String>,
+fn begin_loop(
+    mut terminal: Terminal<Backend>,
+    mut app: App,
     mut events: Events,
-) -> Result<(Vec<String>, Regex), Box<dyn Error>> {
+) -> Result<App, IreError> {
     loop {
         // Draw UI
-        terminal
-            .draw(|f| {
-                let chunks = Layout::default()
-                    .direction(Direction::Vertical)
-                    .margin(2)
-                    .constraints(
-                        [
-                            Constraint::Length(1),
-                            Constraint::Length(3),
-                            Constraint::Min(1),
-                        ]
-                        .as_ref(),
-                    )
-                    .split(f.size());
-
-                let (msg, style) = match app.input.mode {
-                    InputMode::Normal => (
-                        vec![
-                            Span::raw("Press "),
-                            Span::styled("q", Style::default().add_modifier(Modifier::BOLD)),
-                            Span::raw(" to exit, "),
-                            Span::styled("i", Style::default().add_modifier(Modifier::BOLD)),
-                            Span::raw(" to start editing."),
-                        ],
-                        Style::default().add_modifier(Modifier::RAPID_BLINK),
-                    ),
-                    InputMode::Editing => (
-                        vec![
-                            Span::raw("Press "),
-                            Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
-                            Span::raw(" to stop editing, "),
-                            Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
-                            Span::raw(" to record the message"),
-                        ],
-                        Style::default(),
-                    ),
-                };
-                let mut text = Text::from(Spans::from(msg));
-                text.patch_style(style);
-                let help_message = Paragraph::new(text);
-                f.render_widget(help_message, chunks[0]);
-
-                let input = Paragraph::new(app.input.text.as_ref())
-                    .style(match app.input.mode {
-                        InputMode::Normal => Style::default(),
-                        InputMode::Editing => Style::default().fg(Color::Yellow),
-                    })
-                    .block(Block::default().borders(Borders::ALL).title("Input"));
-                f.render_widget(input, chunks[1]);
-                match app.input.mode {
-                    InputMode::Normal =>
-                        // Hide the cursor. `Frame` does this by default, so we don't need to do anything here
-                        {}
-
-                    InputMode::Editing => {
-                        // Make the cursor visible and ask tui-rs to put it at the specified coordinates after rendering
-                        f.set_cursor(
-                            // Put cursor past the end of the input text
-                            chunks[1].x + *app.input.idx() as u16 + 1,
-                            // Move one line down, from the border to the input line
-                            chunks[1].y + 1,
-                        )
+        terminal.draw(|f| {
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .margin(2)
+                .constraints(
+                    [
+                        Constraint::Length(1),
+                        Constraint::Length(3),
+                        Constraint::Length(if app.error.is_some() { 1 } else { 0 }),
+                        Constraint::Length(
+                            if app.focus == Field::Replacement || app.template().is_some() {
+                                3
+                            } else {
+                                0
+                            },
+                        ),
+                        Constraint::Min(1),
+                        Constraint::Length(1),
+                    ]
+                    .as_ref(),
+                )
+                .split(f.size());
+
+            let (msg, style) = match app.mode() {
+                InputMode::Normal => (
+                    vec![
+                        Span::raw("Press "),
+                        Span::styled("q", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to exit, "),
+                        Span::styled("i", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to start editing, "),
+                        Span::styled("r", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to replace, "),
+                        Span::styled("v", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to invert, "),
+                        Span::styled("p", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" for snippets, "),
+                        Span::styled("u", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to undo, "),
+                        Span::styled("I/M/S/X/U", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to toggle flags, "),
+                        Span::styled("j/k", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to scroll."),
+                    ],
+                    Style::default().add_modifier(Modifier::RAPID_BLINK),
+                ),
+                InputMode::Editing if app.picker.is_some() => (
+                    vec![
+                        Span::raw("Press "),
+                        Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to insert, "),
+                        Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to close, "),
+                        Span::styled("Up/Down", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to choose a snippet."),
+                    ],
+                    Style::default(),
+                ),
+                InputMode::Editing if app.search.is_some() => {
+                    let found = app.search.as_ref().and_then(|search| search.found);
+                    let mut msg = vec![
+                        Span::raw("Press "),
+                        Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to use, "),
+                        Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to cancel, "),
+                        Span::styled("Ctrl-R", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" for older."),
+                    ];
+                    if let Some(entry) = found.and_then(|found| app.history.get(found)) {
+                        msg.push(Span::styled(
+                            format!(
+                                " Used {} on {}",
+                                entry.used_at.format("%Y-%m-%d %H:%M"),
+                                entry.files.join(", ")
+                            ),
+                            gutter_style(),
+                        ));
                     }
+                    (msg, Style::default())
                 }
+                InputMode::Editing => (
+                    vec![
+                        Span::raw("Press "),
+                        Span::styled("Esc", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to stop editing, "),
+                        Span::styled("Tab", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to switch field, "),
+                        Span::styled("Up/Down", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" or "),
+                        Span::styled("Ctrl-R", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" for history, "),
+                        Span::styled("Ctrl-P", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" for snippets, "),
+                        Span::styled("Enter", Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(" to record the message"),
+                    ],
+                    Style::default(),
+                ),
+            };
+            let mut text = Text::from(Spans::from(msg));
+            text.patch_style(style);
+            let help_message = Paragraph::new(text);
+            f.render_widget(help_message, chunks[0]);
+
+            let input = Paragraph::new(app.input_spans())
+                .style(match app.input.mode {
+                    InputMode::Normal => Style::default(),
+                    InputMode::Editing => Style::default().fg(Color::Yellow),
+                })
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(Spans::from(vec![
+                            Span::raw("Input "),
+                            Span::styled(app.flags.to_string(), Style::default().fg(Color::Cyan)),
+                            Span::raw(match &app.search {
+                                Some(search)
+                                    if search.found.is_none() && !search.query.is_empty() =>
+                                {
+                                    format!(" (failing reverse-i-search)`{}'", search.query)
+                                }
+                                Some(search) => format!(" (reverse-i-search)`{}'", search.query),
+                                None => String::new(),
+                            }),
+                        ])),
+                );
+            f.render_widget(input, chunks[1]);
+            let replacement = Paragraph::new(app.replacement.text.as_ref())
+                .style(match app.replacement.mode {
+                    InputMode::Normal => Style::default(),
+                    InputMode::Editing => Style::default().fg(Color::Yellow),
+                })
+                .block(Block::default().borders(Borders::ALL).title("Replace with"));
+            f.render_widget(replacement, chunks[3]);
+            let (field, field_chunk) = match app.focus {
+                Field::Pattern => (&app.input, chunks[1]),
+                Field::Replacement => (&app.replacement, chunks[3]),
+            };
+            match field.mode {
+                InputMode::Normal =>
+                    // Hide the cursor. `Frame` does this by default, so we don't need to do anything here
+                    {}
+
+                InputMode::Editing => {
+                    // Make the cursor visible and ask tui-rs to put it at the specified coordinates after rendering
+                    f.set_cursor(
+                        // Put cursor past the end of the input text
+                        field_chunk.x + field.cursor() as u16 + 1,
+                        // Move one line down, from the border to the input line
+                        field_chunk.y + 1,
+                    )
+                }
+            }
 
-                match Regex::new(&app.input.text) {
-                    Ok(re) => app.re = re,
-                    Err(_) => {}
+            // with a replacement, the replaced lines are shown right of the lines
+            let results = match app.template() {
+                Some(_) => Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                    .split(chunks[4]),
+                None => vec![chunks[4]],
+            };
+
+            // only the lines that fit on screen are read from the store
+            let matched = app.index.matched();
+            let height = chunks[4].height.saturating_sub(2) as usize;
+            app.page = (height / app.context.rows_per_match()).max(1);
+            let window = app.scroll.window(app.page, matched.len());
+            let store = app.store.read().unwrap();
+            let rows: Vec<Row<Line>> =
+                context_rows(matched, window.clone(), app.context, store.len())
+                    .into_iter()
+                    .filter_map(|row| match row {
+                        Row::Match(i) => store.get(i).ok().map(Row::Match),
+                        Row::Context(i) => store.get(i).ok().map(Row::Context),
+                        Row::Break => Some(Row::Break),
+                    })
+                    .collect();
+            drop(store);
+            // every match in the window has a single row, between its context
+            let selected = rows
+                .iter()
+                .enumerate()
+                .filter(|(_, row)| matches!(row, Row::Match(_)))
+                .nth(app.scroll.selected() - window.start)
+                .map(|(i, _)| i);
+            // the origin of each line, aligned in a gutter left of the lines
+            let width = rows
+                .iter()
+                .map(|row| match row {
+                    Row::Match(line) | Row::Context(line) => line.origin.to_string().len(),
+                    Row::Break => 0,
+                })
+                .max()
+                .unwrap_or(0);
+            let gutter =
+                |line: &Line| Span::styled(format!("{:>1$} ", line.origin, width), gutter_style());
+            let context_style = Style::default().add_modifier(Modifier::DIM);
+            // records of several lines take up several rows, the gutter is only
+            // shown on the first
+            let item = |line: &Line, spans: Spans| {
+                let mut rows = split_rows(spans);
+                for (i, row) in rows.iter_mut().enumerate() {
+                    let gutter = match i {
+                        0 => gutter(line),
+                        _ => Span::raw(" ".repeat(width + 1)),
+                    };
+                    row.0.insert(0, gutter);
                 }
-                let matches = filter_matches(&contents, &app.re);
-                let pattern_matches = into_matchsets(&matches, &app.re);
-                let pattern_matches: Vec<ListItem> = pattern_matches
+                ListItem::new(Text::from(rows))
+            };
+            let pattern_matches: Vec<ListItem> = rows
+                .iter()
+                .map(|row| match row {
+                    Row::Match(line) => item(line, MatchSet::from_line(line, &app.re).style()),
+                    Row::Context(line) => {
+                        item(line, Spans::from(Span::styled(line.text(), context_style)))
+                    }
+                    Row::Break => ListItem::new(Span::styled(CONTEXT_BREAK, gutter_style())),
+                })
+                .collect();
+            let mut title = Spans::from("Messages");
+            if app.re.captures_len() > 1 {
+                title.0.push(Span::raw(" | Groups: "));
+                title.0.extend(legend(&app.re).0);
+            }
+            if app.invert {
+                title.0.push(Span::raw(" | Inverted"));
+            }
+            if !app.index.is_done() {
+                title
+                    .0
+                    .push(Span::raw(format!(" | Searching {}%", app.index.progress())));
+            }
+            let mut pattern_matches = List::new(pattern_matches)
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+            if let Some(err) = &app.error {
+                let error = Paragraph::new(Span::styled(
+                    format!(" {}", err),
+                    Style::default().fg(Color::Red),
+                ));
+                f.render_widget(error, chunks[2]);
+                // the results are still those of the last pattern that compiled
+                title.0.push(Span::styled(
+                    format!(" | Stale, showing matches of {}", app.re.as_str()),
+                    Style::default().fg(Color::Red),
+                ));
+                pattern_matches =
+                    pattern_matches.style(Style::default().add_modifier(Modifier::DIM));
+            }
+            let pattern_matches =
+                pattern_matches.block(Block::default().borders(Borders::ALL).title(title));
+            let mut state = ListState::default();
+            state.select(selected);
+            f.render_stateful_widget(pattern_matches, results[0], &mut state);
+
+            if let Some(template) = app.template() {
+                let replaced: Vec<ListItem> = rows
                     .iter()
-                    .map(|color_styles| color_styles.style())
-                    .map(|spans| ListItem::new(Spans::from(spans)))
+                    .map(|row| match row {
+                        Row::Match(line) => {
+                            style_replaced(&MatchSet::from_line(line, &app.re), template)
+                        }
+                        Row::Context(line) => Spans::from(Span::styled(line.text(), context_style)),
+                        Row::Break => Spans::from(Span::styled(CONTEXT_BREAK, gutter_style())),
+                    })
+                    .map(|spans| ListItem::new(Text::from(split_rows(spans))))
                     .collect();
-                let pattern_matches = List::new(pattern_matches)
-                    .block(Block::default().borders(Borders::ALL).title("Messages"));
-                f.render_widget(pattern_matches, chunks[2]);
-            })
-            .expect("Failure on draw");
+                let replaced = List::new(replaced)
+                    .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+                    .block(Block::default().borders(Borders::ALL).title("Replaced"));
+                f.render_stateful_widget(replaced, results[1], &mut state);
+            }
+
+            let status = match matched.len() {
+                0 => "no matching lines".to_string(),
+                len => format!("match {} of {} lines", app.scroll.selected() + 1, len),
+            };
+            f.render_widget(
+                Paragraph::new(Span::styled(
+                    status,
+                    Style::default().add_modifier(Modifier::BOLD),
+                )),
+                chunks[5],
+            );
+
+            if let Some(picker) = app.picker {
+                let area = f.size();
+                let height = (app.library.len() as u16 + 2).min(area.height);
+                let width = (area.width * 3 / 4).max(area.width.min(40));
+                let popup = Rect::new(
+                    (area.width - width) / 2,
+                    (area.height - height) / 2,
+                    width,
+                    height,
+                );
+                let name_width = app.library.snippets().map(|(name, _)| name.len()).max();
+                let snippets: Vec<ListItem> = app
+                    .library
+                    .snippets()
+                    .map(|(name, pattern)| {
+                        ListItem::new(Spans::from(vec![
+                            Span::styled(
+                                format!("{:width$}  ", name, width = name_width.unwrap_or(0)),
+                                Style::default().add_modifier(Modifier::BOLD),
+                            ),
+                            Span::styled(pattern.to_string(), gutter_style()),
+                        ]))
+                    })
+                    .collect();
+                let snippets = List::new(snippets)
+                    .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+                    .block(Block::default().borders(Borders::ALL).title("Snippets"));
+                let mut state = ListState::default();
+                state.select(Some(picker.selected()));
+                f.render_widget(Clear, popup);
+                f.render_stateful_widget(snippets, popup, &mut state);
+            }
+        })?;
 
         // Handle input
-        if let Event::Input(input) = events.next().expect("Failure on input") {
-            match app.input.mode {
-                InputMode::Normal => match input {
-                    Key::Char('i') => {
-                        app.input.mode = InputMode::Editing;
-                        events.disable_exit_key();
-                    }
-                    Key::Char('q') => {
-                        panic!("Exiting without writing result")
-                    }
-                    _ => {}
+        let input = match events.next().map_err(io::Error::other)? {
+            Event::Input(input) => input,
+            Event::Lines(lines) => {
+                app.store.write().unwrap().push_lines(lines);
+                app.catch_up();
+                continue;
+            }
+            Event::Matches(update) => {
+                app.index.apply(update);
+                app.catch_up();
+                continue;
+            }
+            // the input can't be searched any further
+            Event::Error(err) => return Err(IreError::read(err)),
+            Event::Tick => continue,
+        };
+        let text = app.input.text.clone();
+        match app.mode() {
+            InputMode::Normal => match input {
+                Key::Char('i') => {
+                    app.edit(Field::Pattern);
+                    events.disable_exit_key();
+                }
+                Key::Char('r') => {
+                    app.edit(Field::Replacement);
+                    events.disable_exit_key();
+                }
+                Key::Char('q') => return Err(IreError::Quit),
+                Key::Char('u') => app.focused().undo(),
+                Key::Ctrl('r') => app.focused().redo(),
+                Key::Char('p') => {
+                    app.edit(Field::Pattern);
+                    events.disable_exit_key();
+                    app.picker = Some(Scroll::default());
+                }
+                Key::Char('j') | Key::Down => app.scroll.down(1, app.index.matched().len()),
+                Key::Char('k') | Key::Up => app.scroll.up(1),
+                Key::PageDown => app.scroll.down(app.page, app.index.matched().len()),
+                Key::PageUp => app.scroll.up(app.page),
+                Key::Char('v') => {
+                    app.invert = !app.invert;
+                    app.search();
+                }
+                Key::Char(c) if flag_of(c).is_some() => {
+                    app.flags.toggle(flag_of(c).unwrap());
+                    app.update_pattern(true);
+                }
+                Key::Char('g') | Key::Home => app.scroll.top(),
+                Key::Char('G') | Key::End => app.scroll.bottom(app.index.matched().len()),
+                _ => {}
+            },
+            InputMode::Editing if app.picker.is_some() => app.pick_snippet(input),
+            InputMode::Editing if app.search.is_some() => app.search_history(input),
+            InputMode::Editing => match input {
+                Key::Up if app.focus == Field::Pattern => {
+                    let recalled = app
+                        .history
+                        .previous(&app.input.text, app.flags)
+                        .map(|(pattern, flags)| (pattern.to_string(), flags));
+                    app.recall(recalled);
+                }
+                Key::Down if app.focus == Field::Pattern => {
+                    let recalled = app
+                        .history
+                        .next(&app.input.text)
+                        .map(|(pattern, flags)| (pattern.to_string(), flags));
+                    app.recall(recalled);
+                }
+                Key::Ctrl('p') if app.focus == Field::Pattern => {
+                    app.picker = Some(Scroll::default())
+                }
+                Key::Ctrl('r') if app.focus == Field::Pattern => {
+                    app.search = Some(Search {
+                        original: app.input.text.clone(),
+                        original_flags: app.flags,
+                        ..Search::default()
+                    });
+                }
+                // the pattern doesn't compile, so there is no result to write yet
+                Key::Char('\n') if app.error.is_some() => {}
+                Key::Char('\n') => {
+                    app.focused().enter();
+                    return Ok(app);
+                }
+                Key::Char('\t') => match app.focus {
+                    Field::Pattern => app.edit(Field::Replacement),
+                    Field::Replacement => app.edit(Field::Pattern),
                 },
-                InputMode::Editing => match input {
-                    Key::Char('\n') => return Ok((contents.to_vec(), app.re)),
-                    Key::Alt(',') => app.input.previous_boundary(),
-                    Key::Alt('.') => app.input.next_boundary(),
-                    Key::Char(c) => {
-                        app.input.add(c);
-                    }
-                    Key::Backspace => match app.input.idx() {
-                        0 => {}
-                        1..=400 => {
-                            app.input.delete();
-                        }
-                        _ => {}
-                    },
-                    Key::Esc => {
-                        app.input.mode = InputMode::Normal;
-                        events.enable_exit_key();
-                    }
-                    Key::Left => {
-                        app.input.left();
-                    }
+                Key::Ctrl('z') => app.focused().undo(),
+                Key::Alt('z') => app.focused().redo(),
+                Key::Alt(',') => app.focused().previous_boundary(),
+                Key::Alt('.') => app.focused().next_boundary(),
+                Key::Alt('b') => app.focused().previous_word(),
+                Key::Alt('f') => app.focused().next_word(),
+                Key::Alt('d') => app.focused().kill_next_word(),
+                Key::Ctrl('w') => app.focused().kill_previous_word(),
+                Key::Ctrl('u') => app.focused().kill_to_start(),
+                Key::Ctrl('k') => app.focused().kill_to_end(),
+                Key::Ctrl('y') => app.focused().yank(),
+                Key::Alt('y') => app.focused().yank_pop(),
+                Key::Ctrl('t') => app.focused().transpose(),
+                Key::Char(c) => {
+                    app.focused().add(c);
+                }
+                Key::Backspace => app.focused().delete(),
+                Key::Delete => app.focused().delete_next(),
+                Key::Esc => {
+                    app.focused().esc();
+                    events.enable_exit_key();
+                }
+                Key::Left | Key::Ctrl('b') => {
+                    app.focused().left();
+                }
 
-                    Key::Right => app.input.right(),
-                    Key::Home => app.input.home(),
-                    Key::End => app.input.end(),
-                    _ => {}
-                },
-            }
+                Key::Right | Key::Ctrl('f') => app.focused().right(),
+                Key::Home | Key::Ctrl('a') => app.focused().home(),
+                Key::End | Key::Ctrl('e') => app.focused().end(),
+                _ => {}
+            },
+        }
+        if app.input.text != text {
+            app.update_pattern(false);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::EXIT_ERROR;
+    use crate::source::into_lines;
+
+    /// Number of matching lines and what was written
+    fn written(args: Vec<&str>, pattern: &str, content: &str) -> (usize, String) {
+        let matches = arguments(args).unwrap();
+        let query = Query {
+            re: Regex::new(pattern).unwrap(),
+            invert: matches.is_present("INVERT"),
+        };
+        let lines = into_lines("app.log", content).into_iter().map(Ok);
+        // without --pattern the result is written after the interactive ui
+        let crafted = match matches.is_present("PATTERN") {
+            true => None,
+            false => Some(pattern),
+        };
+        let context = context_of(&matches).unwrap();
+        let mut out = Vec::new();
+        let matched =
+            write_result(&mut out, &matches, &query, None, lines, context, crafted).unwrap();
+        (matched, String::from_utf8(out).unwrap())
+    }
+
+    fn result_of(args: Vec<&str>, pattern: &str, content: &str) -> String {
+        written(args, pattern, content).1
+    }
+
+    #[test]
+    fn source_is_chosen_from_arguments() {
+        let file = arguments(vec!["ire", "app.log"]).unwrap();
+        assert_eq!(Source::File("app.log"), source_of(&file));
+        let glob = arguments(vec!["ire", "-g", "*.log"]).unwrap();
+        assert_eq!(Source::Glob("*.log"), source_of(&glob));
+        let stdin = arguments(vec!["ire", "-"]).unwrap();
+        assert_eq!(Source::Stdin, source_of(&stdin));
+        let none = arguments(vec!["ire"]).unwrap();
+        assert_eq!(Source::Stdin, source_of(&none));
+    }
+
+    #[test]
+    fn batch_mode_writes_matching_lines() {
+        let args = vec!["ire", "-e", r"\d", "app.log"];
+        assert_eq!("a1\nc3\n", result_of(args, r"\d", "a1\nb\nc3"));
+    }
+
+    #[test]
+    fn batch_mode_writes_format_asked_for() {
+        let args = vec!["ire", "-e", r"(?P<key>\w)=\d", "-f", "csv", "app.log"];
+        assert_eq!(
+            "file,line,key\napp.log,1,a\napp.log,1,b\n",
+            result_of(args, r"(?P<key>\w)=\d", "a=1 b=2\nc")
+        );
+    }
+
+    #[test]
+    fn batch_mode_writes_context_between_breaks() {
+        let args = vec!["ire", "-e", r"\d", "-A", "1", "app.log"];
+        assert_eq!(
+            "b1\nc\n--\nf2\n",
+            result_of(args, r"\d", "a\nb1\nc\nd\ne\nf2")
+        );
+    }
+
+    #[test]
+    fn batch_mode_can_invert_match() {
+        let args = vec!["ire", "-e", r"\d", "-v", "app.log"];
+        assert_eq!("b\n", result_of(args, r"\d", "a1\nb\nc3"));
+    }
+
+    #[test]
+    fn output_file_defaults_to_csv() {
+        let args = vec!["ire", "-e", r"(\d)", "-o", "out.csv", "app.log"];
+        let expected = "file,line,group1\napp.log,1,1\n";
+        assert_eq!(expected, result_of(args, r"(\d)", "a1\nb"));
+    }
+
+    #[test]
+    fn interactive_result_ends_with_pattern() {
+        let expected = format!("a1\nLines were matched with: {}\n", r"\d".green());
+        assert_eq!(expected, result_of(vec!["ire", "app.log"], r"\d", "a1\nb"));
+    }
+
+    #[test]
+    fn exit_code_tells_whether_lines_matched() {
+        let args = || vec!["ire", "-e", r"\d", "app.log"];
+        let (matched, _) = written(args(), r"\d", "a1\nb\nc3");
+        assert_eq!(0, exit_code(&Ok(matched)));
+        let (matched, _) = written(args(), r"\d", "a\nb");
+        assert_eq!(EXIT_NO_MATCH, exit_code(&Ok(matched)));
+        assert_eq!(EXIT_ERROR, exit_code(&Err(IreError::NoInput)));
+    }
+
+    #[test]
+    fn bad_number_of_context_lines_is_an_error() {
+        let matches = arguments(vec!["ire", "-C", "x", "-e", "a", "app.log"]).unwrap();
+        let err = IreError::from(context_of(&matches).unwrap_err());
+        assert_eq!(EXIT_ERROR, err.exit_code());
+    }
+
+    #[test]
+    fn unknown_option_is_an_error() {
+        let err = arguments(vec!["ire", "--nope", "app.log"]).unwrap_err();
+        assert_eq!(EXIT_ERROR, err.exit_code());
+    }
+
+    #[test]
+    fn context_lines_default_to_both_sides() {
+        let matches = arguments(vec!["ire", "-C", "2", "-A", "1", "app.log"]).unwrap();
+        let context = context_of(&matches).unwrap();
+        assert_eq!(
+            Context {
+                before: 2,
+                after: 1
+            },
+            context
+        );
+    }
+}
@@ -0,0 +1,323 @@
+use std::{borrow::Cow, error::Error, io::Write};
+
+use colored::Colorize;
+use csv::{Writer, WriterBuilder};
+use regex::bytes::Regex;
+use serde::{ser::SerializeMap, Serialize, Serializer};
+
+use crate::capture::{group_names, MatchSet, RecordLayout};
+
+/// File formats the captured groups can be written in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// The matched lines as they are
+    Text,
+    Csv,
+    Tsv,
+    /// A single JSON array with one object per matched line
+    Json,
+    /// One JSON object per matched line, separated by newlines
+    Ndjson,
+}
+
+impl Format {
+    pub fn from_arg(arg: &str) -> Option<Format> {
+        match arg {
+            "text" => Some(Format::Text),
+            "csv" => Some(Format::Csv),
+            "tsv" => Some(Format::Tsv),
+            "json" => Some(Format::Json),
+            "ndjson" => Some(Format::Ndjson),
+            _ => None,
+        }
+    }
+}
+
+/// Written between lines that are apart when context is shown
+pub const CONTEXT_BREAK: &str = "--";
+
+/// Columns written before the groups in the tabular formats, locating the line
+const ORIGIN_COLUMNS: [&str; 2] = ["file", "line"];
+
+/// Values of `ORIGIN_COLUMNS` for `match_set`, empty when its origin is unknown
+fn origin_columns(match_set: &MatchSet) -> Vec<String> {
+    match match_set.origin {
+        Some(origin) => vec![origin.path.to_string(), origin.line_number.to_string()],
+        None => vec![String::new(), String::new()],
+    }
+}
+
+/// Rows of groups to write for `match_set`. A line written for not matching, see
+/// `--invert-match`, has no groups but still gets a row
+fn records(match_set: &MatchSet, layout: RecordLayout, width: usize) -> Vec<Vec<String>> {
+    match match_set.to_records(layout) {
+        records if records.is_empty() => vec![vec![String::new(); width]],
+        records => records,
+    }
+}
+
+/// Groups of a match by name, written in the order of the pattern rather than sorted
+/// like a `serde_json::Map`
+struct Groups<'a>(Vec<(&'a str, Option<Cow<'a, str>>)>);
+
+impl Serialize for Groups<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, group) in &self.0 {
+            map.serialize_entry(name, group)?;
+        }
+        map.end()
+    }
+}
+
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    file: Option<&'a str>,
+    line: Option<usize>,
+    text: Cow<'a, str>,
+    matches: Vec<Groups<'a>>,
+}
+
+impl<'a> JsonLine<'a> {
+    fn from(match_set: &'a MatchSet, names: &'a [String]) -> Self {
+        let matches = match_set
+            .to_captures()
+            .into_iter()
+            .map(|row| Groups(names.iter().map(String::as_str).zip(row).collect()))
+            .collect();
+        JsonLine {
+            file: match_set.origin.map(|origin| &*origin.path),
+            line: match_set.origin.map(|origin| origin.line_number),
+            text: String::from_utf8_lossy(match_set.full_text),
+            matches,
+        }
+    }
+}
+
+enum Sink<W: Write> {
+    /// CSV or TSV, quoting fields that hold the delimiter or a newline
+    Csv(Box<Writer<W>>),
+    Plain(W),
+}
+
+/// Writes matched lines one at a time, so that results never have to be held in
+/// memory. `layout` decides how lines with several matches are written in the tabular
+/// formats, JSON always lists every match. Every format but text tells where each
+/// line was read from
+pub struct MatchWriter<W: Write> {
+    sink: Sink<W>,
+    format: Format,
+    layout: RecordLayout,
+    names: Vec<String>,
+    /// Template that matches are replaced with in text output
+    template: Option<String>,
+    /// Whether context lines are dimmed, when written to a terminal
+    dim: bool,
+    written: usize,
+}
+
+impl<W: Write> MatchWriter<W> {
+    pub fn new(out: W, format: Format, layout: RecordLayout, re: &Regex) -> Self {
+        let sink = match format {
+            Format::Csv => Sink::Csv(Box::new(Writer::from_writer(out))),
+            Format::Tsv => Sink::Csv(Box::new(
+                WriterBuilder::new().delimiter(b'\t').from_writer(out),
+            )),
+            _ => Sink::Plain(out),
+        };
+        MatchWriter {
+            sink,
+            format,
+            layout,
+            names: group_names(re),
+            template: None,
+            dim: false,
+            written: 0,
+        }
+    }
+
+    /// Writes text lines with every match replaced by `template`
+    pub fn replacing(mut self, template: &str) -> Self {
+        self.template = Some(template.to_string());
+        self
+    }
+
+    /// Dims context lines in text output
+    pub fn dimming(mut self) -> Self {
+        self.dim = true;
+        self
+    }
+
+    /// Writes a line shown around a match. Only text output has context, the other
+    /// formats hold nothing but matches
+    pub fn write_context(&mut self, line: &str) -> Result<(), Box<dyn Error>> {
+        if let (Sink::Plain(out), Format::Text) = (&mut self.sink, self.format) {
+            match self.dim {
+                true => writeln!(out, "{}", line.dimmed())?,
+                false => writeln!(out, "{}", line)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the separator between lines that are apart, like grep
+    pub fn write_break(&mut self) -> Result<(), Box<dyn Error>> {
+        self.write_context(CONTEXT_BREAK)
+    }
+
+    fn write_header(&mut self) -> Result<(), Box<dyn Error>> {
+        let header = ORIGIN_COLUMNS
+            .iter()
+            .map(|column| column.to_string())
+            .chain(self.names.iter().cloned());
+        match (&mut self.sink, self.format) {
+            (Sink::Csv(writer), _) => writer.write_record(header)?,
+            (Sink::Plain(out), Format::Json) => write!(out, "[")?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn write(&mut self, match_set: &MatchSet) -> Result<(), Box<dyn Error>> {
+        if self.written == 0 {
+            self.write_header()?;
+        }
+        match (&mut self.sink, self.format) {
+            (Sink::Csv(writer), _) => {
+                let origin = origin_columns(match_set);
+                for record in records(match_set, self.layout, self.names.len()) {
+                    writer.write_record(origin.iter().chain(record.iter()))?;
+                }
+            }
+            (Sink::Plain(out), Format::Json) => {
+                if self.written > 0 {
+                    write!(out, ",")?;
+                }
+                writeln!(out)?;
+                serde_json::to_writer(&mut *out, &JsonLine::from(match_set, &self.names))?;
+            }
+            (Sink::Plain(out), Format::Ndjson) => {
+                serde_json::to_writer(&mut *out, &JsonLine::from(match_set, &self.names))?;
+                writeln!(out)?;
+            }
+            (Sink::Plain(out), _) => match &self.template {
+                Some(template) => writeln!(out, "{}", match_set.replaced(template))?,
+                None => writeln!(out, "{}", match_set.raw_line())?,
+            },
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Ends the output, the header is still written when nothing matched
+    pub fn finish(mut self) -> Result<W, Box<dyn Error>> {
+        if self.written == 0 {
+            self.write_header()?;
+        }
+        match self.sink {
+            Sink::Csv(writer) => Ok(writer.into_inner().map_err(|err| err.to_string())?),
+            Sink::Plain(mut out) => {
+                if self.format == Format::Json {
+                    writeln!(out, "\n]")?;
+                }
+                out.flush()?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::{filter_matches, into_matchsets};
+    use crate::source::{into_lines, Line};
+
+    macro_rules! test_write_matches {
+        ($($func_name:ident: $values:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    // Given
+                    let (format, layout, re, content, expected) = $values;
+                    let re = Regex::new(re).unwrap();
+                    let lines: Vec<Line> = filter_matches(into_lines("app.log", content).into_iter().map(Ok), &re, false)
+                        .map(|line| line.unwrap().1)
+                        .collect();
+                    let match_sets = into_matchsets(&lines, &re);
+                    let mut writer = MatchWriter::new(Vec::new(), format, layout, &re);
+                    // When
+                    for match_set in match_sets.iter() {
+                        writer.write(match_set).unwrap();
+                    }
+                    let out = writer.finish().unwrap();
+                    // Then
+                    assert_eq!(expected, String::from_utf8(out).unwrap())
+                }
+            )*
+        }
+    }
+
+    test_write_matches! {
+        csv_starts_with_header_row : (Format::Csv, RecordLayout::Rows, r"(?P<key>\w)=(\d)", "a=1 b=2\nnope", "file,line,key,group2\napp.log,1,a,1\napp.log,1,b,2\n"),
+        csv_rows_locate_their_line : (Format::Csv, RecordLayout::Joined, r"(\d)", "x\na=1 b=2", "file,line,group1\napp.log,2,1;2\n"),
+        tsv_joins_values_per_group : (Format::Tsv, RecordLayout::Joined, r"(?P<key>\w)=(\d)", "a=1 b=2", "file\tline\tkey\tgroup2\napp.log\t1\ta;b\t1;2\n"),
+        tsv_rows_locate_their_line : (Format::Tsv, RecordLayout::Rows, r"(\d)", "x\n1", "file\tline\tgroup1\napp.log\t2\t1\n"),
+        tsv_quotes_fields_with_tab : (Format::Tsv, RecordLayout::Rows, r"(a\tb)", "a\tb", "file\tline\tgroup1\napp.log\t1\t\"a\tb\"\n"),
+        ndjson_writes_one_object_per_line : (Format::Ndjson, RecordLayout::Rows, r"(?P<key>\w)=(\d)?", "x\na= b=2", concat!(
+            r#"{"file":"app.log","line":2,"text":"a= b=2","matches":[{"key":"a","group2":null},{"key":"b","group2":"2"}]}"#,
+            "\n",
+        )),
+        json_writes_array_of_objects : (Format::Json, RecordLayout::Rows, r"(\d)", "1\n2", concat!(
+            "[\n",
+            r#"{"file":"app.log","line":1,"text":"1","matches":[{"group1":"1"}]},"#,
+            "\n",
+            r#"{"file":"app.log","line":2,"text":"2","matches":[{"group1":"2"}]}"#,
+            "\n]\n",
+        )),
+        json_keeps_groups_in_pattern_order : (Format::Ndjson, RecordLayout::Rows, r"(?P<value>\d)(?P<key>\w)", "1a", concat!(
+            r#"{"file":"app.log","line":1,"text":"1a","matches":[{"value":"1","key":"a"}]}"#,
+            "\n",
+        )),
+        json_without_matches_is_empty_array : (Format::Json, RecordLayout::Rows, r"(\d)", "a", "[\n]\n"),
+        csv_without_matches_still_has_header : (Format::Csv, RecordLayout::Rows, r"(?P<digit>\d)", "a", "file,line,digit\n"),
+        csv_without_groups_writes_whole_match : (Format::Csv, RecordLayout::Rows, r"\d+ms", "took 12ms, then 345ms", "file,line,match\napp.log,1,12ms\napp.log,1,345ms\n"),
+        text_writes_matched_lines : (Format::Text, RecordLayout::Rows, r"\d", "a1\nb\nc3", "a1\nc3\n"),
+    }
+
+    #[test]
+    fn only_text_writes_context() {
+        let re = Regex::new(r"(\d)").unwrap();
+        let write = |format| {
+            let mut writer = MatchWriter::new(Vec::new(), format, RecordLayout::Rows, &re);
+            writer.write_context("before").unwrap();
+            writer.write(&MatchSet::from("1", &re)).unwrap();
+            writer.write_break().unwrap();
+            String::from_utf8(writer.finish().unwrap()).unwrap()
+        };
+        assert_eq!("before\n1\n--\n", write(Format::Text));
+        assert_eq!("file,line,group1\n,,1\n", write(Format::Csv));
+    }
+
+    #[test]
+    fn tsv_quotes_fields_with_newline() {
+        let re = Regex::new(r"(a\nb)").unwrap();
+        let mut writer = MatchWriter::new(Vec::new(), Format::Tsv, RecordLayout::Rows, &re);
+        writer.write(&MatchSet::from("a\nb", &re)).unwrap();
+        let out = writer.finish().unwrap();
+        assert_eq!(
+            "file\tline\tgroup1\n\t\t\"a\nb\"\n",
+            String::from_utf8(out).unwrap()
+        )
+    }
+
+    #[test]
+    fn replacing_writes_substituted_lines() {
+        let re = Regex::new(r"(?P<key>\w)=(\d)").unwrap();
+        let mut writer = MatchWriter::new(Vec::new(), Format::Text, RecordLayout::Rows, &re)
+            .replacing("$2:${key}");
+        writer.write(&MatchSet::from("a=1 b=2", &re)).unwrap();
+        let out = writer.finish().unwrap();
+        assert_eq!("1:a 2:b\n", String::from_utf8(out).unwrap())
+    }
+}
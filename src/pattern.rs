@@ -0,0 +1,243 @@
+use std::{error::Error, fmt, ops::Range};
+
+use regex::bytes::{Regex, RegexBuilder};
+use regex_syntax::ParserBuilder;
+use serde::{Deserialize, Serialize};
+
+/// Regex flags that can be toggled instead of written inline in the pattern
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flag {
+    CaseInsensitive,
+    MultiLine,
+    DotMatchesNewLine,
+    IgnoreWhitespace,
+    Unicode,
+}
+
+/// Flags the pattern is compiled with. The defaults are those of `Regex::new`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Flags {
+    pub case_insensitive: bool,
+    pub multi_line: bool,
+    pub dot_matches_new_line: bool,
+    pub ignore_whitespace: bool,
+    pub unicode: bool,
+}
+
+impl Default for Flags {
+    fn default() -> Flags {
+        Flags {
+            case_insensitive: false,
+            multi_line: false,
+            dot_matches_new_line: false,
+            ignore_whitespace: false,
+            unicode: true,
+        }
+    }
+}
+
+impl Flags {
+    pub fn toggle(&mut self, flag: Flag) {
+        let value = match flag {
+            Flag::CaseInsensitive => &mut self.case_insensitive,
+            Flag::MultiLine => &mut self.multi_line,
+            Flag::DotMatchesNewLine => &mut self.dot_matches_new_line,
+            Flag::IgnoreWhitespace => &mut self.ignore_whitespace,
+            Flag::Unicode => &mut self.unicode,
+        };
+        *value = !*value;
+    }
+
+    fn builder(&self, pattern: &str) -> RegexBuilder {
+        let mut builder = RegexBuilder::new(pattern);
+        builder
+            .case_insensitive(self.case_insensitive)
+            .multi_line(self.multi_line)
+            .dot_matches_new_line(self.dot_matches_new_line)
+            .ignore_whitespace(self.ignore_whitespace)
+            .unicode(self.unicode);
+        builder
+    }
+
+    fn parser(&self) -> regex_syntax::Parser {
+        ParserBuilder::new()
+            .case_insensitive(self.case_insensitive)
+            .multi_line(self.multi_line)
+            .dot_matches_new_line(self.dot_matches_new_line)
+            .ignore_whitespace(self.ignore_whitespace)
+            .unicode(self.unicode)
+            .allow_invalid_utf8(true)
+            .build()
+    }
+}
+
+/// Flags that differ from the defaults, written as an inline group such as `(?ix-u)`
+/// that gives the same regex when put before the pattern
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == Flags::default() {
+            return Ok(());
+        }
+        let enabled: String = [
+            (self.case_insensitive, 'i'),
+            (self.multi_line, 'm'),
+            (self.dot_matches_new_line, 's'),
+            (self.ignore_whitespace, 'x'),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, c)| c)
+        .collect();
+        let disabled = if self.unicode { "" } else { "-u" };
+        write!(f, "(?{}{})", enabled, disabled)
+    }
+}
+
+/// Why a pattern doesn't compile, and where in the pattern the problem is
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternError {
+    pub message: String,
+    /// Byte range of the offending part of the pattern, when it is known
+    pub span: Option<Range<usize>>,
+}
+
+impl PatternError {
+    fn from(pattern: &str, flags: Flags, err: regex::Error) -> Self {
+        // `regex::Error` only has a preformatted message, parse again for the span
+        let (message, span) = match flags.parser().parse(pattern) {
+            Err(regex_syntax::Error::Parse(err)) => (err.kind().to_string(), Some(*err.span())),
+            Err(regex_syntax::Error::Translate(err)) => (err.kind().to_string(), Some(*err.span())),
+            _ => (err.to_string(), None),
+        };
+        PatternError {
+            message,
+            span: span.map(|span| widen(pattern, span.start.offset..span.end.offset)),
+        }
+    }
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for PatternError {}
+
+/// Empty spans point between characters, widen them to the character after so they
+/// can be highlighted. At the end of the pattern the span is past the last character
+fn widen(pattern: &str, span: Range<usize>) -> Range<usize> {
+    if !span.is_empty() {
+        return span;
+    }
+    let width = pattern[span.start..]
+        .chars()
+        .next()
+        .map_or(1, char::len_utf8);
+    span.start..span.start + width
+}
+
+/// Compiles `pattern` with `flags`, explaining what is wrong with it when it doesn't
+/// compile
+pub fn compile(pattern: &str, flags: Flags) -> Result<Regex, PatternError> {
+    flags
+        .builder(pattern)
+        .build()
+        .map_err(|err| PatternError::from(pattern, flags, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! test_compile_error {
+        ($($func_name:ident: $values:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    let (pattern, message, span): (&str, &str, Option<Range<usize>>) = $values;
+                    let err = compile(pattern, Flags::default()).unwrap_err();
+                    assert_eq!(message, err.message);
+                    assert_eq!(span, err.span);
+                }
+            )*
+        }
+    }
+
+    test_compile_error! {
+        unclosed_group_points_at_open_paren : ("a(b", "unclosed group", Some(1..2)),
+        unclosed_class_points_at_open_bracket : ("x[ab", "unclosed character class", Some(1..2)),
+        bad_escape_points_at_escape : (r"a\qb", "unrecognized escape sequence", Some(1..3)),
+        missing_repetition_operand : ("*a", "repetition operator missing expression", Some(0..1)),
+        unknown_unicode_property_is_a_translate_error : (r"\p{Nope}", "Unicode property not found", Some(0..8)),
+    }
+
+    #[test]
+    fn valid_pattern_compiles() {
+        let re = compile(r"(\w)=(\d)", Flags::default()).unwrap();
+        assert_eq!(r"(\w)=(\d)", re.as_str());
+    }
+
+    fn flags_of(toggled: &[Flag]) -> Flags {
+        let mut flags = Flags::default();
+        for &flag in toggled {
+            flags.toggle(flag);
+        }
+        flags
+    }
+
+    #[test]
+    fn toggled_flags_change_what_matches() {
+        let re = compile(
+            "a b",
+            flags_of(&[Flag::CaseInsensitive, Flag::IgnoreWhitespace]),
+        );
+        assert!(re.unwrap().is_match(b"xAB"));
+        let re = compile("^b", flags_of(&[Flag::MultiLine])).unwrap();
+        assert!(re.is_match(b"a\nb"));
+        let re = compile("a.b", flags_of(&[Flag::DotMatchesNewLine])).unwrap();
+        assert!(re.is_match(b"a\nb"));
+    }
+
+    #[test]
+    fn without_unicode_bytes_that_are_not_utf8_match() {
+        let re = compile(r"caf\xe9", Flags::default()).unwrap();
+        assert!(re.is_match("café".as_bytes()));
+        assert!(!re.is_match(b"caf\xe9"));
+        let re = compile(r"caf\xe9", flags_of(&[Flag::Unicode])).unwrap();
+        assert!(re.is_match(b"caf\xe9"));
+    }
+
+    #[test]
+    fn toggling_twice_restores_flag() {
+        assert_eq!(
+            Flags::default(),
+            flags_of(&[Flag::MultiLine, Flag::MultiLine])
+        );
+    }
+
+    #[test]
+    fn flags_are_shown_as_inline_group() {
+        assert_eq!("", Flags::default().to_string());
+        assert_eq!(
+            "(?ix-u)",
+            flags_of(&[Flag::IgnoreWhitespace, Flag::Unicode, Flag::CaseInsensitive]).to_string()
+        );
+    }
+
+    #[test]
+    fn error_span_follows_flags() {
+        assert!(compile(r"\pL", Flags::default()).is_ok());
+        let err = compile(r"\pL", flags_of(&[Flag::Unicode])).unwrap_err();
+        assert_eq!(Some(0..3), err.span);
+        let err = compile(r"a (", flags_of(&[Flag::IgnoreWhitespace])).unwrap_err();
+        assert_eq!(Some(2..3), err.span);
+    }
+
+    #[test]
+    fn empty_span_is_widened_past_the_end() {
+        assert_eq!(3..4, widen("abc", 3..3));
+        assert_eq!(1..3, widen("aéc", 1..1));
+    }
+}
@@ -0,0 +1,137 @@
+use std::{io, iter::Peekable};
+
+use regex::bytes::Regex;
+
+use crate::source::Line;
+
+/// How lines are grouped into the records that a pattern runs over
+#[derive(Debug, Clone)]
+pub enum Grouping {
+    /// Every line is a record of its own
+    Lines,
+    /// Each file is a single record
+    Buffer,
+    /// A record starts at every line matching the regex, e.g. the timestamp that
+    /// starts a log entry, and goes on until the next one
+    Start(Box<Regex>),
+}
+
+/// Joins the lines of `lines` into records, see `Grouping`. The lines of a record are
+/// joined by newlines, so patterns can match across lines, and the record keeps the
+/// origin of its first line. Records never span more than one file
+pub struct Records<I: Iterator> {
+    lines: Peekable<I>,
+    grouping: Grouping,
+}
+
+impl<I> Records<I>
+where
+    I: Iterator<Item = io::Result<Line>>,
+{
+    pub fn new(lines: I, grouping: Grouping) -> Self {
+        Records {
+            lines: lines.peekable(),
+            grouping,
+        }
+    }
+}
+
+/// Whether `line` belongs to `record` rather than starting a new one
+fn continues(grouping: &Grouping, record: &Line, line: &Line) -> bool {
+    line.origin.path == record.origin.path
+        && match grouping {
+            Grouping::Lines => false,
+            Grouping::Buffer => true,
+            Grouping::Start(re) => !re.is_match(&line.bytes),
+        }
+}
+
+impl<I> Iterator for Records<I>
+where
+    I: Iterator<Item = io::Result<Line>>,
+{
+    type Item = io::Result<Line>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = match self.lines.next()? {
+            Ok(line) => line,
+            Err(err) => return Some(Err(err)),
+        };
+        // a read error ends the record, and is passed on by the next call
+        while let Some(Ok(line)) = self.lines.peek() {
+            if !continues(&self.grouping, &record, line) {
+                break;
+            }
+            if let Some(Ok(line)) = self.lines.next() {
+                record.bytes.push(b'\n');
+                record.bytes.extend_from_slice(&line.bytes);
+            }
+        }
+        Some(Ok(record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::into_lines;
+
+    macro_rules! test_records {
+        ($($func_name:ident: $values:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    // Given
+                    let (grouping, files, expected): (Grouping, Vec<(&str, &str)>, Vec<(&str, &str, usize)>) = $values;
+                    let lines = files
+                        .into_iter()
+                        .flat_map(|(path, content)| into_lines(path, content))
+                        .map(Ok);
+                    // When
+                    let records: Vec<Line> = Records::new(lines, grouping).map(Result::unwrap).collect();
+                    // Then
+                    let actual: Vec<(&str, &str, usize)> = records
+                        .iter()
+                        .map(|record| (&*record.origin.path, std::str::from_utf8(&record.bytes).unwrap(), record.origin.line_number))
+                        .collect();
+                    assert_eq!(expected, actual);
+                }
+            )*
+        }
+    }
+
+    test_records! {
+        lines_are_records_of_their_own : (Grouping::Lines, vec![("a.log", "one\ntwo")], vec![
+            ("a.log", "one", 1),
+            ("a.log", "two", 2),
+        ]),
+        buffer_is_one_record_per_file : (Grouping::Buffer, vec![("a.log", "one\ntwo"), ("b.log", "three")], vec![
+            ("a.log", "one\ntwo", 1),
+            ("b.log", "three", 1),
+        ]),
+        record_starts_at_matching_line : (Grouping::Start(Box::new(Regex::new(r"^\d").unwrap())), vec![("a.log", "1 error\n  at foo\n  at bar\n2 ok")], vec![
+            ("a.log", "1 error\n  at foo\n  at bar", 1),
+            ("a.log", "2 ok", 4),
+        ]),
+        lines_before_first_start_are_a_record : (Grouping::Start(Box::new(Regex::new(r"^\d").unwrap())), vec![("a.log", "header\n1 ok")], vec![
+            ("a.log", "header", 1),
+            ("a.log", "1 ok", 2),
+        ]),
+        record_ends_with_its_file : (Grouping::Start(Box::new(Regex::new(r"^\d").unwrap())), vec![("a.log", "1 a\ncont"), ("b.log", "cont")], vec![
+            ("a.log", "1 a\ncont", 1),
+            ("b.log", "cont", 1),
+        ]),
+    }
+
+    #[test]
+    fn read_error_ends_record_and_is_passed_on() {
+        let lines = vec![
+            Ok(into_lines("a.log", "one").remove(0)),
+            Err(io::Error::other("broken")),
+        ];
+        let mut records = Records::new(lines.into_iter(), Grouping::Buffer);
+        assert_eq!("one", records.next().unwrap().unwrap().text());
+        assert!(records.next().unwrap().is_err());
+        assert!(records.next().is_none());
+    }
+}
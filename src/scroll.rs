@@ -0,0 +1,89 @@
+use std::ops::Range;
+
+/// Selected line of a list that is longer than the screen, and the first line shown.
+/// Only the lines in `window` need to be read, so the list itself is never held here
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Scroll {
+    selected: usize,
+    offset: usize,
+}
+
+impl Scroll {
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn down(&mut self, lines: usize, len: usize) {
+        self.selected = (self.selected + lines).min(len.saturating_sub(1));
+    }
+
+    pub fn up(&mut self, lines: usize) {
+        self.selected = self.selected.saturating_sub(lines);
+    }
+
+    pub fn top(&mut self) {
+        self.selected = 0;
+    }
+
+    pub fn bottom(&mut self, len: usize) {
+        self.selected = len.saturating_sub(1);
+    }
+
+    /// Range of the list to show in `height` lines, scrolled just enough to keep the
+    /// selected line on screen
+    pub fn window(&mut self, height: usize, len: usize) -> Range<usize> {
+        self.selected = self.selected.min(len.saturating_sub(1));
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if height > 0 && self.selected >= self.offset + height {
+            self.offset = self.selected + 1 - height;
+        }
+        // don't leave empty space below the list when it shrinks
+        self.offset = self.offset.min(len.saturating_sub(height));
+        self.offset..(self.offset + height).min(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! test_scroll {
+        ($($func_name:ident: $values:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    let (moves, height, len, selected, window): (&dyn Fn(&mut Scroll), usize, usize, usize, Range<usize>) = $values;
+                    let mut scroll = Scroll::default();
+                    moves(&mut scroll);
+                    assert_eq!(window, scroll.window(height, len));
+                    assert_eq!(selected, scroll.selected());
+                }
+            )*
+        }
+    }
+
+    test_scroll! {
+        starts_at_top : (&|_| {}, 3, 10, 0, 0..3),
+        short_list_fits_window : (&|_| {}, 3, 2, 0, 0..2),
+        empty_list_has_empty_window : (&|s| s.down(1, 0), 3, 0, 0, 0..0),
+        down_within_window_does_not_scroll : (&|s| s.down(2, 10), 3, 10, 2, 0..3),
+        down_past_window_scrolls : (&|s| s.down(4, 10), 3, 10, 4, 2..5),
+        down_stops_at_last_line : (&|s| s.down(20, 10), 3, 10, 9, 7..10),
+        up_stops_at_first_line : (&|s| { s.down(1, 10); s.up(5) }, 3, 10, 0, 0..3),
+        bottom_shows_last_page : (&|s| s.bottom(10), 3, 10, 9, 7..10),
+        top_after_bottom : (&|s| { s.bottom(10); s.top() }, 3, 10, 0, 0..3),
+        selection_is_clamped_when_list_shrinks : (&|s| s.bottom(10), 3, 4, 3, 1..4),
+    }
+
+    #[test]
+    fn up_scrolls_only_once_selection_leaves_window() {
+        let mut scroll = Scroll::default();
+        scroll.bottom(10);
+        scroll.window(3, 10);
+        scroll.up(1);
+        assert_eq!(7..10, scroll.window(3, 10));
+        scroll.up(2);
+        assert_eq!(6..9, scroll.window(3, 10));
+    }
+}
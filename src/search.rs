@@ -0,0 +1,315 @@
+use std::{
+    cmp::min,
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, RwLock,
+    },
+    thread,
+};
+
+use regex::bytes::Regex;
+use termion::event::Key;
+
+use crate::capture::filter_matches;
+use crate::event::Event;
+use crate::store::{LineStore, SharedStore};
+
+/// Number of lines matched between progress reports, and between checks for whether
+/// the search has been cancelled
+const BATCH_SIZE: usize = 10_000;
+
+/// Lines matched by a batch of a search
+#[derive(Debug, PartialEq)]
+pub struct MatchUpdate {
+    pub generation: usize,
+    /// Store indices of the matching lines in the batch
+    pub matched: Vec<usize>,
+    /// Number of lines searched so far
+    pub scanned: usize,
+    /// Number of lines in the store when the batch was searched
+    pub total: usize,
+    pub done: bool,
+}
+
+/// What lines a search looks for
+#[derive(Debug, Clone)]
+pub struct Query {
+    pub re: Regex,
+    /// Look for the lines that don't match instead
+    pub invert: bool,
+}
+
+struct Job {
+    generation: usize,
+    query: Query,
+    from: usize,
+}
+
+/// Matches the lines of a `LineStore` in a background thread, reporting results as
+/// `Event::Matches`. Every new search gets a new generation, which cancels the searches
+/// of earlier generations
+pub struct Searcher {
+    jobs: mpsc::Sender<Job>,
+    generation: Arc<AtomicUsize>,
+}
+
+impl Searcher {
+    pub fn new(store: SharedStore, tx: mpsc::Sender<Event<Key>>) -> Self {
+        let (jobs, rx) = mpsc::channel::<Job>();
+        let generation = Arc::new(AtomicUsize::new(0));
+        {
+            let generation = generation.clone();
+            thread::spawn(move || {
+                while let Ok(mut job) = rx.recv() {
+                    // only the latest job is worth doing
+                    while let Ok(newer) = rx.try_recv() {
+                        job = newer;
+                    }
+                    if !search(&store, &job, &generation, &tx) {
+                        return;
+                    }
+                }
+            });
+        }
+        Searcher { jobs, generation }
+    }
+
+    /// Starts matching every line against `query`, cancelling the current search.
+    /// Returns the generation of the new search
+    pub fn search(&self, query: Query) -> usize {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let _ = self.jobs.send(Job {
+            generation,
+            query,
+            from: 0,
+        });
+        generation
+    }
+
+    /// Continues the current search from line `from`, e.g. after lines were added
+    pub fn resume(&self, query: Query, from: usize) {
+        let _ = self.jobs.send(Job {
+            generation: self.generation.load(Ordering::SeqCst),
+            query,
+            from,
+        });
+    }
+}
+
+/// Matches lines in batches until the end of the store, or until a newer search
+/// starts. A line that can't be read ends the search with an `Event::Error`.
+/// Returns false once nobody listens for the results
+fn search(
+    store: &RwLock<LineStore>,
+    job: &Job,
+    generation: &AtomicUsize,
+    tx: &mpsc::Sender<Event<Key>>,
+) -> bool {
+    let mut from = job.from;
+    loop {
+        if generation.load(Ordering::SeqCst) != job.generation {
+            return true;
+        }
+        // the lock is released between batches, so lines can be added meanwhile
+        let store = store.read().unwrap();
+        let total = store.len();
+        let end = min(total, from + BATCH_SIZE);
+        let lines = store.iter_from(from).take(end - from);
+        let matched = filter_matches(lines, &job.query.re, job.query.invert)
+            .map(|line| line.map(|(i, _)| from + i))
+            .collect::<io::Result<_>>();
+        drop(store);
+        let matched = match matched {
+            Ok(matched) => matched,
+            Err(err) => return tx.send(Event::Error(err)).is_ok(),
+        };
+
+        let update = MatchUpdate {
+            generation: job.generation,
+            matched,
+            scanned: end,
+            total,
+            done: end == total,
+        };
+        if tx.send(Event::Matches(update)).is_err() {
+            return false;
+        }
+        if end == total {
+            return true;
+        }
+        from = end;
+    }
+}
+
+/// Store indices of the lines matching the current search, filled in as the
+/// `Searcher` reports them
+pub struct MatchIndex {
+    generation: usize,
+    matched: Vec<usize>,
+    scanned: usize,
+    total: usize,
+    done: bool,
+}
+
+impl MatchIndex {
+    pub fn new() -> Self {
+        MatchIndex {
+            generation: 0,
+            matched: Vec::new(),
+            scanned: 0,
+            total: 0,
+            done: false,
+        }
+    }
+
+    /// Drops the results of the previous search, waiting for those of `generation`
+    pub fn reset(&mut self, generation: usize) {
+        self.generation = generation;
+        self.matched.clear();
+        self.scanned = 0;
+        self.total = 0;
+        self.done = false;
+    }
+
+    /// Adds the results of `update`, unless they belong to a cancelled search
+    pub fn apply(&mut self, update: MatchUpdate) {
+        if update.generation != self.generation {
+            return;
+        }
+        self.matched.extend(update.matched);
+        self.scanned = update.scanned;
+        self.total = update.total;
+        self.done = update.done;
+    }
+
+    /// Marks the search as running again, for lines added after it was done
+    pub fn resume(&mut self) {
+        self.done = false;
+    }
+
+    pub fn matched(&self) -> &[usize] {
+        &self.matched
+    }
+
+    pub fn scanned(&self) -> usize {
+        self.scanned
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Share of the lines searched so far, in percent
+    pub fn progress(&self) -> usize {
+        match self.total {
+            0 => 100,
+            total => self.scanned * 100 / total,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::into_lines;
+
+    fn query(pattern: &str, invert: bool) -> Query {
+        Query {
+            re: Regex::new(pattern).unwrap(),
+            invert,
+        }
+    }
+
+    fn store_of(content: &str) -> SharedStore {
+        let mut store = LineStore::new();
+        store.push_lines(into_lines("app.log", content));
+        Arc::new(RwLock::new(store))
+    }
+
+    /// Applies updates until the search is done
+    fn wait_for(index: &mut MatchIndex, rx: &mpsc::Receiver<Event<Key>>) {
+        while !index.is_done() {
+            if let Event::Matches(update) = rx.recv().unwrap() {
+                index.apply(update);
+            }
+        }
+    }
+
+    #[test]
+    fn search_reports_matching_lines() {
+        let store = store_of("a1\nb\nc3");
+        let (tx, rx) = mpsc::channel();
+        let searcher = Searcher::new(store, tx);
+        let mut index = MatchIndex::new();
+
+        index.reset(searcher.search(query(r"\d", false)));
+        wait_for(&mut index, &rx);
+
+        assert_eq!(&[0, 2], index.matched());
+        assert_eq!(100, index.progress());
+    }
+
+    #[test]
+    fn inverted_search_reports_lines_not_matching() {
+        let store = store_of("a1\nb\nc3");
+        let (tx, rx) = mpsc::channel();
+        let searcher = Searcher::new(store, tx);
+        let mut index = MatchIndex::new();
+
+        index.reset(searcher.search(query(r"\d", true)));
+        wait_for(&mut index, &rx);
+
+        assert_eq!(&[1], index.matched());
+    }
+
+    #[test]
+    fn resume_only_matches_added_lines() {
+        let store = store_of("a1\nb");
+        let (tx, rx) = mpsc::channel();
+        let searcher = Searcher::new(store.clone(), tx);
+        let mut index = MatchIndex::new();
+        let query = query(r"\d", false);
+        index.reset(searcher.search(query.clone()));
+        wait_for(&mut index, &rx);
+
+        store
+            .write()
+            .unwrap()
+            .push_lines(into_lines("app.log", "c3"));
+        index.resume();
+        searcher.resume(query, index.scanned());
+        wait_for(&mut index, &rx);
+
+        assert_eq!(&[0, 2], index.matched());
+    }
+
+    #[test]
+    fn updates_of_cancelled_search_are_ignored() {
+        let mut index = MatchIndex::new();
+        index.reset(2);
+        index.apply(MatchUpdate {
+            generation: 1,
+            matched: vec![4],
+            scanned: 5,
+            total: 5,
+            done: true,
+        });
+        assert!(index.matched().is_empty());
+        assert!(!index.is_done());
+    }
+
+    #[test]
+    fn progress_of_partial_search() {
+        let mut index = MatchIndex::new();
+        index.reset(1);
+        index.apply(MatchUpdate {
+            generation: 1,
+            matched: vec![],
+            scanned: 25,
+            total: 100,
+            done: false,
+        });
+        assert_eq!(25, index.progress());
+    }
+}
@@ -0,0 +1,512 @@
+use std::{
+    borrow::Cow,
+    error::Error,
+    fmt,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read},
+    iter,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use glob::glob;
+use regex::bytes;
+
+use crate::encoding::Encoding;
+
+/// Where the input is read from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source<'a> {
+    File(&'a str),
+    Glob(&'a str),
+    Stdin,
+}
+
+impl<'a> Source<'a> {
+    /// Streams the records of every input file, one at a time, as ended by `delimiter`
+    /// once decoded from `encoding`
+    pub fn records(
+        &self,
+        delimiter: &Delimiter,
+        encoding: Encoding,
+    ) -> Box<dyn Iterator<Item = io::Result<Line>> + 'a> {
+        match *self {
+            Source::File(filename) => {
+                file_records(Path::new(filename), delimiter.clone(), encoding)
+            }
+            Source::Glob(pattern) => {
+                let delimiter = delimiter.clone();
+                Box::new(
+                    glob_paths(pattern, encoding)
+                        .into_iter()
+                        .flat_map(move |path| file_records(&path, delimiter.clone(), encoding)),
+                )
+            }
+            Source::Stdin => Box::new(LineReader::with_delimiter(
+                BufReader::new(encoding.decode(io::stdin())),
+                Arc::from(STDIN_PATH),
+                delimiter.clone(),
+            )),
+        }
+    }
+}
+
+impl Source<'_> {
+    /// Names of what is read, kept with the pattern in the history. Files are named by
+    /// their absolute path, so they can be told apart from any directory
+    pub fn names(&self) -> Vec<String> {
+        match *self {
+            Source::File(filename) => vec![fs::canonicalize(filename)
+                .map_or(filename.to_string(), |path| path.display().to_string())],
+            Source::Glob(pattern) => vec![pattern.to_string()],
+            Source::Stdin => vec![STDIN_PATH.to_string()],
+        }
+    }
+}
+
+fn file_records(
+    path: &Path,
+    delimiter: Delimiter,
+    encoding: Encoding,
+) -> Box<dyn Iterator<Item = io::Result<Line>>> {
+    let name = path.to_string_lossy();
+    match File::open(path) {
+        Ok(file) => Box::new(LineReader::with_delimiter(
+            BufReader::new(encoding.decode(file)),
+            Arc::from(name.as_ref()),
+            delimiter,
+        )),
+        Err(err) => Box::new(iter::once(Err(ReadError::wrap(&name, err)))),
+    }
+}
+
+/// Where one record of the input ends and the next begins
+#[derive(Debug, Clone)]
+pub enum Delimiter {
+    /// Lines ended by `\n` or `\r\n`
+    Newline,
+    /// Records ended by this string, e.g. `\0`
+    Bytes(Vec<u8>),
+    /// Records separated by every match of the regex, e.g. blank lines
+    Regex(Box<bytes::Regex>),
+}
+
+/// Bytes of `arg` with the escapes `\0`, `\n`, `\r`, `\t` and `\\` replaced, so that
+/// delimiters can be given on the command line
+pub fn unescape(arg: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut chars = arg.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('0') => '\0',
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('\\') => '\\',
+                Some(other) => {
+                    bytes.push(b'\\');
+                    other
+                }
+                None => '\\',
+            },
+            c => c,
+        };
+        let mut buf = [0; 4];
+        bytes.extend(c.encode_utf8(&mut buf).bytes());
+    }
+    bytes
+}
+
+/// Where a line was read from. `line_number` starts at 1
+#[derive(Debug, Clone, PartialEq)]
+pub struct Origin {
+    pub path: Arc<str>,
+    pub line_number: usize,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.path, self.line_number)
+    }
+}
+
+/// A line of input together with its origin. The bytes are kept as they were read,
+/// so that a pattern can match bytes that are not valid UTF-8
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub bytes: Vec<u8>,
+    pub origin: Origin,
+}
+
+impl Line {
+    /// Text of the line, with bytes that are not valid UTF-8 shown as `�`
+    pub fn text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.bytes)
+    }
+}
+
+/// Reading the input at `path` failed. It is carried inside an `io::Error`, so that it
+/// passes through the iterators of lines, see `ReadError::wrap`
+#[derive(Debug)]
+pub struct ReadError {
+    pub path: String,
+    pub source: io::Error,
+}
+
+impl ReadError {
+    /// `source` as an `io::Error` that tells it happened while reading `path`
+    pub fn wrap(path: &str, source: io::Error) -> io::Error {
+        if source.get_ref().is_some_and(|err| err.is::<ReadError>()) {
+            return source;
+        }
+        let kind = source.kind();
+        io::Error::new(
+            kind,
+            ReadError {
+                path: path.to_string(),
+                source,
+            },
+        )
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.source)
+    }
+}
+
+impl Error for ReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Splits `content` into lines, numbering them from 1
+#[cfg(test)]
+pub fn into_lines(path: &str, content: &str) -> Vec<Line> {
+    let path: Arc<str> = Arc::from(path);
+    content
+        .split('\n')
+        .enumerate()
+        .map(|(i, text)| Line {
+            bytes: text.as_bytes().to_vec(),
+            origin: Origin {
+                path: path.clone(),
+                line_number: i + 1,
+            },
+        })
+        .collect()
+}
+
+/// Bytes of a line without the `\r` of a `\r\n` line ending
+pub fn line_bytes(bytes: &[u8]) -> Vec<u8> {
+    bytes.strip_suffix(b"\r").unwrap_or(bytes).to_vec()
+}
+
+/// `bytes` with every `\r\n` replaced by `\n`
+fn crlf_to_lf(bytes: &[u8]) -> Vec<u8> {
+    let mut lf = Vec::with_capacity(bytes.len());
+    for (i, &b) in bytes.iter().enumerate() {
+        if b != b'\r' || bytes.get(i + 1) != Some(&b'\n') {
+            lf.push(b);
+        }
+    }
+    lf
+}
+
+/// Bytes searched again for a `Delimiter::Regex` when more input is read, as a match
+/// may have started before the end of the last search. Delimiters matched by a regex
+/// are expected to be shorter than this
+const REGEX_LOOKBACK: usize = 1024;
+
+/// First match of `re` in `haystack` that is not empty, from `start` on
+fn first_non_empty<'h>(
+    re: &bytes::Regex,
+    haystack: &'h [u8],
+    mut start: usize,
+) -> Option<bytes::Match<'h>> {
+    while start <= haystack.len() {
+        let m = re.find_at(haystack, start)?;
+        if m.start() != m.end() {
+            return Some(m);
+        }
+        start = m.end() + 1;
+    }
+    None
+}
+
+/// Reads lines, or records ended by a `Delimiter`, from `reader` one at a time, so
+/// that input of any size can be streamed. Bytes that are not valid UTF-8 are kept as
+/// they are rather than failing the whole input, and `\r\n` line endings become `\n`
+pub struct LineReader<R> {
+    reader: R,
+    path: Arc<str>,
+    /// Number of the line the next record starts on
+    line_number: usize,
+    delimiter: Delimiter,
+    /// Bytes read but not yet returned as a record
+    buf: Vec<u8>,
+    /// Length of the start of `buf` already searched for a delimiter, so that a long
+    /// record isn't searched again every time more of it is read
+    scanned: usize,
+    eof: bool,
+}
+
+impl<R: BufRead> LineReader<R> {
+    /// `line_number` is the number of the first line read
+    pub fn new(reader: R, path: Arc<str>, line_number: usize) -> Self {
+        LineReader {
+            reader,
+            path,
+            line_number,
+            delimiter: Delimiter::Newline,
+            buf: Vec::new(),
+            scanned: 0,
+            eof: false,
+        }
+    }
+
+    /// Reads records ended by `delimiter`, starting on the first line
+    pub fn with_delimiter(reader: R, path: Arc<str>, delimiter: Delimiter) -> Self {
+        LineReader {
+            delimiter,
+            ..LineReader::new(reader, path, 1)
+        }
+    }
+
+    /// Range of the first delimiter in `buf`, once it is certain that more input
+    /// won't change where it ends
+    fn find_delimiter(&mut self) -> Option<(usize, usize)> {
+        let found = match &self.delimiter {
+            Delimiter::Newline => self.buf[self.scanned..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .map(|i| (self.scanned + i, self.scanned + i + 1)),
+            Delimiter::Bytes(delimiter) if delimiter.is_empty() => None,
+            Delimiter::Bytes(delimiter) => {
+                // the end of the last scan may hold the start of a delimiter
+                let from = self.scanned.saturating_sub(delimiter.len() - 1);
+                self.buf[from..]
+                    .windows(delimiter.len())
+                    .position(|window| window == &delimiter[..])
+                    .map(|i| (from + i, from + i + delimiter.len()))
+            }
+            Delimiter::Regex(re) => {
+                let from = self.scanned.saturating_sub(REGEX_LOOKBACK);
+                first_non_empty(re, &self.buf, from)
+                    .filter(|m| self.eof || m.end() < self.buf.len())
+                    .map(|m| (m.start(), m.end()))
+            }
+        };
+        if found.is_none() {
+            self.scanned = self.buf.len();
+        }
+        found
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let read = {
+            let data = self.reader.fill_buf()?;
+            self.buf.extend_from_slice(data);
+            data.len()
+        };
+        self.reader.consume(read);
+        self.eof = read == 0;
+        Ok(())
+    }
+
+    fn record(&mut self, end: usize, consumed: usize) -> Line {
+        let bytes = match self.delimiter {
+            Delimiter::Newline => line_bytes(&self.buf[..end]),
+            _ => crlf_to_lf(&self.buf[..end]),
+        };
+        let line = Line {
+            bytes,
+            origin: Origin {
+                path: self.path.clone(),
+                line_number: self.line_number,
+            },
+        };
+        let consumed: Vec<u8> = self.buf.drain(..consumed).collect();
+        self.scanned = 0;
+        self.line_number += match self.delimiter {
+            Delimiter::Newline => 1,
+            _ => consumed.iter().filter(|&&b| b == b'\n').count(),
+        };
+        line
+    }
+}
+
+impl<R: BufRead> Iterator for LineReader<R> {
+    type Item = io::Result<Line>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((end, consumed)) = self.find_delimiter() {
+                return Some(Ok(self.record(end, consumed)));
+            }
+            if self.eof {
+                // the last record is not ended by a delimiter
+                return match self.buf.len() {
+                    0 => None,
+                    len => Some(Ok(self.record(len, len))),
+                };
+            }
+            if let Err(err) = self.fill() {
+                return Some(Err(ReadError::wrap(&self.path, err)));
+            }
+        }
+    }
+}
+
+/// Name used as path for lines read from stdin
+pub const STDIN_PATH: &str = "<stdin>";
+
+/// Whether `filename` asks for input from stdin, following the convention of `-`
+pub fn is_stdin(filename: &str) -> bool {
+    filename == "-"
+}
+
+/// Paths of the files matching `pattern`, in the order returned by `glob`. Files that
+/// can't be read as text in `encoding`, such as images or executables, are skipped
+pub fn glob_paths(pattern: &str, encoding: Encoding) -> Vec<PathBuf> {
+    // invalid patterns are turned down when the source is chosen
+    let paths = match glob(pattern) {
+        Ok(paths) => paths,
+        Err(_) => return Vec::new(),
+    };
+    paths
+        .filter_map(Result::ok)
+        .filter(|path| {
+            let binary = is_binary(path, encoding);
+            if binary {
+                eprintln!("Skipping binary file: {}", path.display());
+            }
+            !binary
+        })
+        .collect()
+}
+
+/// Whether the file at `path` looks like binary data, judged by its first 8 KiB.
+/// Files that can't be read are left for reading to report
+fn is_binary(path: &Path, encoding: Encoding) -> bool {
+    let mut head = Vec::new();
+    match File::open(path).and_then(|file| file.take(8 * 1024).read_to_end(&mut head)) {
+        Ok(_) => encoding.is_binary(&head),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_numbered_from_1_and_share_path() {
+        let lines = into_lines("log.txt", "first\nsecond");
+        assert_eq!(2, lines.len());
+        assert_eq!("second", lines[1].text());
+        assert_eq!(2, lines[1].origin.line_number);
+        assert_eq!("log.txt", &*lines[1].origin.path);
+        assert!(Arc::ptr_eq(&lines[0].origin.path, &lines[1].origin.path));
+    }
+
+    #[test]
+    fn origin_is_shown_as_path_and_line_number() {
+        let lines = into_lines("logs/app.log", "first\nsecond");
+        assert_eq!("logs/app.log:2", lines[1].origin.to_string());
+    }
+
+    #[test]
+    fn line_reader_strips_newlines_and_does_not_add_trailing_empty_line() {
+        let reader = LineReader::new("first\n\nthird\n".as_bytes(), Arc::from("log.txt"), 5);
+        let lines: Vec<(String, usize)> = reader
+            .map(|line| line.unwrap())
+            .map(|line| (line.text().into_owned(), line.origin.line_number))
+            .collect();
+        assert_eq!(
+            vec![
+                ("first".to_string(), 5),
+                ("".to_string(), 6),
+                ("third".to_string(), 7)
+            ],
+            lines
+        );
+    }
+
+    fn records(delimiter: Delimiter, content: &str) -> Vec<(String, usize)> {
+        LineReader::with_delimiter(content.as_bytes(), Arc::from("log.txt"), delimiter)
+            .map(|line| line.unwrap())
+            .map(|line| (line.text().into_owned(), line.origin.line_number))
+            .collect()
+    }
+
+    macro_rules! test_delimiter {
+        ($($func_name:ident: $values:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    let (delimiter, content, expected): (Delimiter, &str, Vec<(&str, usize)>) = $values;
+                    let expected: Vec<(String, usize)> = expected
+                        .into_iter()
+                        .map(|(text, line_number)| (text.to_string(), line_number))
+                        .collect();
+                    assert_eq!(expected, records(delimiter, content));
+                }
+            )*
+        }
+    }
+
+    test_delimiter! {
+        crlf_line_endings_are_stripped : (Delimiter::Newline, "a\r\nb\r\n", vec![("a", 1), ("b", 2)]),
+        lone_carriage_return_is_kept : (Delimiter::Newline, "a\rb\n", vec![("a\rb", 1)]),
+        nul_delimited_records : (Delimiter::Bytes(vec![0]), "a\0b\nc\0", vec![("a", 1), ("b\nc", 1)]),
+        blank_line_delimited_records : (Delimiter::Bytes(b"\n\n".to_vec()), "a\nb\n\nc\n", vec![("a\nb", 1), ("c\n", 4)]),
+        crlf_inside_records_becomes_newline : (Delimiter::Bytes(vec![0]), "a\r\nb\0c", vec![("a\nb", 1), ("c", 2)]),
+        regex_delimited_records : (Delimiter::Regex(Box::new(bytes::Regex::new(r"\n\s*\n").unwrap())), "a\n  \n\nb\nc", vec![("a", 1), ("b\nc", 4)]),
+        regex_delimiter_at_end_is_not_a_record : (Delimiter::Regex(Box::new(bytes::Regex::new(r"\n+").unwrap())), "a\n\nb\n", vec![("a", 1), ("b", 3)]),
+    }
+
+    #[test]
+    fn delimiters_split_between_reads_are_found() {
+        let records = |delimiter| -> Vec<String> {
+            let reader = BufReader::with_capacity(1, "a\n  \n\nb\n\nc".as_bytes());
+            LineReader::with_delimiter(reader, Arc::from("log.txt"), delimiter)
+                .map(|line| line.unwrap().text().into_owned())
+                .collect()
+        };
+        let blank_line = Delimiter::Bytes(b"\n\n".to_vec());
+        assert_eq!(vec!["a\n  ", "b", "c"], records(blank_line));
+        let blank_lines = Delimiter::Regex(Box::new(bytes::Regex::new(r"\n\s*\n").unwrap()));
+        assert_eq!(vec!["a", "b", "c"], records(blank_lines));
+    }
+
+    #[test]
+    fn escapes_in_delimiter_are_replaced() {
+        assert_eq!(b"\0".to_vec(), unescape(r"\0"));
+        assert_eq!(b"\n\n".to_vec(), unescape(r"\n\n"));
+        assert_eq!(b"a\\b\\\t".to_vec(), unescape(r"a\b\\\t"));
+        assert_eq!("é;".as_bytes().to_vec(), unescape("é;"));
+    }
+
+    #[test]
+    fn read_errors_tell_the_path() {
+        let mut records = Source::File("missing.log").records(&Delimiter::Newline, Encoding::Utf8);
+        let err = records.next().unwrap().unwrap_err();
+        assert_eq!(io::ErrorKind::NotFound, err.kind());
+        assert!(err.to_string().starts_with("missing.log: "));
+        let err = ReadError::wrap("other.log", err);
+        assert!(err.to_string().starts_with("missing.log: "));
+    }
+
+    #[test]
+    fn line_reader_keeps_invalid_utf8() {
+        let mut reader = LineReader::new(&b"caf\xe9\r\n"[..], Arc::from("log.txt"), 1);
+        let line = reader.next().unwrap().unwrap();
+        assert_eq!(b"caf\xe9".to_vec(), line.bytes);
+        assert_eq!("caf\u{fffd}", line.text());
+        assert!(reader.next().is_none());
+    }
+}
@@ -0,0 +1,432 @@
+use std::{
+    collections::hash_map::RandomState,
+    env,
+    fs::{self, File, OpenOptions},
+    hash::{BuildHasher, Hasher},
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    iter,
+    os::unix::fs::{FileExt, OpenOptionsExt},
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Mutex, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::encoding::Encoding;
+use crate::source::{
+    glob_paths, line_bytes, Line, LineReader, Origin, ReadError, Source, STDIN_PATH,
+};
+
+/// Lines of a file, located by the byte offset where each line starts. Only the
+/// offsets are kept in memory, lines are read from the file when asked for
+struct IndexedFile {
+    path: Arc<str>,
+    file: File,
+    /// Start of every line, followed by the start of a line that would come after
+    /// the last one
+    starts: Vec<u64>,
+    /// Removed once the store is dropped, used when input that can only be read once
+    /// is spilled to disk
+    _temp: Option<TempFile>,
+}
+
+impl IndexedFile {
+    fn open(path: &Path, name: Arc<str>, temp: Option<TempFile>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let starts = index_lines(&file)?;
+        Ok(IndexedFile {
+            path: name,
+            file,
+            starts,
+            _temp: temp,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.starts.len() - 1
+    }
+
+    fn get(&self, index: usize) -> io::Result<Line> {
+        let start = self.starts[index];
+        // the newline ending the line is not part of it
+        let mut buf = vec![0; (self.starts[index + 1] - start - 1) as usize];
+        self.file
+            .read_exact_at(&mut buf, start)
+            .map_err(|err| ReadError::wrap(&self.path, err))?;
+        Ok(Line {
+            bytes: line_bytes(&buf),
+            origin: Origin {
+                path: self.path.clone(),
+                line_number: index + 1,
+            },
+        })
+    }
+
+    /// Indexes the file at `path` where it is when it can be read at any offset.
+    /// Anything else, like a pipe, is spilled to a temporary file first
+    fn index(path: &Path, name: Arc<str>) -> io::Result<Self> {
+        if fs::metadata(path)?.is_file() {
+            IndexedFile::open(path, name, None)
+        } else {
+            IndexedFile::spill(&mut File::open(path)?, name)
+        }
+    }
+
+    /// Copies `reader` to a temporary file and indexes that instead
+    fn spill<R: Read>(reader: &mut R, name: Arc<str>) -> io::Result<Self> {
+        let (temp, mut file) = TempFile::create("input")?;
+        io::copy(reader, &mut file)?;
+        let path = temp.path.clone();
+        IndexedFile::open(&path, name, Some(temp))
+    }
+
+    fn iter_from(&self, index: usize) -> io::Result<impl Iterator<Item = io::Result<Line>>> {
+        let mut file = self.file.try_clone()?;
+        file.seek(SeekFrom::Start(self.starts[index]))?;
+        // the file may have grown since it was indexed
+        let remaining = self.len() - index;
+        Ok(LineReader::new(BufReader::new(file), self.path.clone(), index + 1).take(remaining))
+    }
+}
+
+/// Byte offsets where each line in `reader` starts, see `IndexedFile::starts`
+fn index_lines<R: Read>(mut reader: R) -> io::Result<Vec<u64>> {
+    let mut starts = vec![0];
+    let mut buf = vec![0; 64 * 1024];
+    let mut offset = 0;
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        starts.extend(
+            buf[..n]
+                .iter()
+                .enumerate()
+                .filter(|(_, &b)| b == b'\n')
+                .map(|(i, _)| offset + i as u64 + 1),
+        );
+        offset += n as u64;
+    }
+    if *starts.last().unwrap() != offset {
+        // the last line is not ended by a newline, pretend that it is
+        starts.push(offset + 1);
+    }
+    Ok(starts)
+}
+
+/// Paths of the temp files that exist, so that they can still be removed when ire
+/// ends without dropping them
+static TEMP_FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Removes the temp files that are in use, for when ire ends without dropping them,
+/// e.g. on a panic
+pub fn remove_temp_files() {
+    // the lock may be held by the thread that panicked
+    if let Ok(paths) = TEMP_FILES.try_lock() {
+        for path in paths.iter() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// A file in the temp directory that is removed when dropped
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Creates a file only the current user can read, under a name that can't be
+    /// guessed so that it can't be replaced by someone else beforehand
+    fn create(name: &str) -> io::Result<(Self, File)> {
+        loop {
+            let path = env::temp_dir().join(format!("ire-{}-{:016x}", name, random()));
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path)
+            {
+                Ok(file) => {
+                    TEMP_FILES.lock().unwrap().push(path.clone());
+                    return Ok((TempFile { path }, file));
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/// A random number, from the randomly seeded keys of `RandomState`
+fn random() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    hasher.write_u128(nanos);
+    hasher.write_u32(process::id());
+    hasher.finish()
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+        if let Ok(mut paths) = TEMP_FILES.lock() {
+            paths.retain(|path| *path != self.path);
+        }
+    }
+}
+
+enum Chunk {
+    File(IndexedFile),
+    /// Lines that can't be read back from a file, e.g. lines added by `--follow`
+    Memory(Vec<Line>),
+}
+
+impl Chunk {
+    fn len(&self) -> usize {
+        match self {
+            Chunk::File(file) => file.len(),
+            Chunk::Memory(lines) => lines.len(),
+        }
+    }
+}
+
+/// Backing store for the input, holding an index of where each line is rather than
+/// the lines themselves, so that files larger than memory can be matched
+pub struct LineStore {
+    chunks: Vec<Chunk>,
+    /// Store index of the first line in each chunk
+    firsts: Vec<usize>,
+    len: usize,
+}
+
+/// A `LineStore` that is searched in the background while lines are still added
+pub type SharedStore = Arc<RwLock<LineStore>>;
+
+impl LineStore {
+    pub fn new() -> Self {
+        LineStore {
+            chunks: Vec::new(),
+            firsts: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Indexes every file in `source`. Stdin, pipes and other input that can't be
+    /// read twice are first copied to a temporary file
+    pub fn from_source(source: &Source) -> io::Result<Self> {
+        let mut store = LineStore::new();
+        match *source {
+            Source::File(filename) => {
+                let file = IndexedFile::index(Path::new(filename), Arc::from(filename))
+                    .map_err(|err| ReadError::wrap(filename, err))?;
+                store.push_chunk(Chunk::File(file));
+            }
+            Source::Glob(pattern) => {
+                for path in glob_paths(pattern, Encoding::Utf8) {
+                    let name: Arc<str> = Arc::from(path.to_string_lossy().as_ref());
+                    let file = IndexedFile::index(&path, name.clone())
+                        .map_err(|err| ReadError::wrap(&name, err))?;
+                    store.push_chunk(Chunk::File(file));
+                }
+            }
+            Source::Stdin => {
+                let file = IndexedFile::spill(&mut io::stdin().lock(), Arc::from(STDIN_PATH))
+                    .map_err(|err| ReadError::wrap(STDIN_PATH, err))?;
+                store.push_chunk(Chunk::File(file));
+            }
+        }
+        Ok(store)
+    }
+
+    fn push_chunk(&mut self, chunk: Chunk) {
+        self.firsts.push(self.len);
+        self.len += chunk.len();
+        self.chunks.push(chunk);
+    }
+
+    /// Adds lines that are only kept in memory
+    pub fn push_lines(&mut self, lines: Vec<Line>) {
+        self.len += lines.len();
+        if let Some(Chunk::Memory(memory)) = self.chunks.last_mut() {
+            memory.extend(lines);
+        } else {
+            self.firsts.push(self.len - lines.len());
+            self.chunks.push(Chunk::Memory(lines));
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Chunk holding the line at `index`, and the index of the line within it
+    fn locate(&self, index: usize) -> (usize, usize) {
+        let chunk = match self.firsts.binary_search(&index) {
+            Ok(mut chunk) => {
+                // skip empty chunks starting at the same index
+                while self.chunks[chunk].len() == 0 {
+                    chunk += 1;
+                }
+                chunk
+            }
+            Err(chunk) => chunk - 1,
+        };
+        (chunk, index - self.firsts[chunk])
+    }
+
+    pub fn get(&self, index: usize) -> io::Result<Line> {
+        let (chunk, local) = self.locate(index);
+        match &self.chunks[chunk] {
+            Chunk::File(file) => file.get(local),
+            Chunk::Memory(lines) => Ok(lines[local].clone()),
+        }
+    }
+
+    /// Streams the lines from `index` to the end of the store
+    pub fn iter_from(&self, index: usize) -> Box<dyn Iterator<Item = io::Result<Line>> + '_> {
+        if index >= self.len {
+            return Box::new(iter::empty());
+        }
+        let (first, local) = self.locate(index);
+        Box::new(self.chunks[first..].iter().enumerate().flat_map(
+            move |(i, chunk)| -> Box<dyn Iterator<Item = io::Result<Line>> + '_> {
+                let start = if i == 0 { local } else { 0 };
+                match chunk {
+                    Chunk::File(file) if start < file.len() => match file.iter_from(start) {
+                        Ok(lines) => Box::new(lines),
+                        Err(err) => Box::new(iter::once(Err(ReadError::wrap(&file.path, err)))),
+                    },
+                    Chunk::File(_) => Box::new(iter::empty()),
+                    Chunk::Memory(lines) => Box::new(lines[start..].iter().cloned().map(Ok)),
+                }
+            },
+        ))
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = io::Result<Line>> + '_> {
+        self.iter_from(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::into_lines;
+    use std::{io::Write, os::unix::fs::PermissionsExt, os::unix::io::AsRawFd};
+
+    fn temp_file(content: &str) -> TempFile {
+        let (temp, _) = TempFile::create("test").unwrap();
+        fs::write(&temp.path, content).unwrap();
+        temp
+    }
+
+    fn file_chunk(content: &str) -> Chunk {
+        let temp = temp_file(content);
+        let path = temp.path.clone();
+        Chunk::File(IndexedFile::open(&path, Arc::from("app.log"), Some(temp)).unwrap())
+    }
+
+    fn texts<I: Iterator<Item = io::Result<Line>>>(lines: I) -> Vec<String> {
+        lines
+            .map(|line| line.unwrap().text().into_owned())
+            .collect()
+    }
+
+    macro_rules! test_index_lines {
+        ($($func_name:ident: $values:expr,)*) => {
+            $(
+                #[test]
+                fn $func_name() {
+                    let (content, expected): (&str, Vec<u64>) = $values;
+                    assert_eq!(expected, index_lines(content.as_bytes()).unwrap());
+                }
+            )*
+        }
+    }
+
+    test_index_lines! {
+        index_of_empty_input_has_no_lines : ("", vec![0]),
+        index_of_lines_ended_by_newline : ("ab\nc\n", vec![0, 3, 5]),
+        index_of_unterminated_last_line : ("ab\nc", vec![0, 3, 5]),
+        index_keeps_empty_lines : ("\n\n", vec![0, 1, 2]),
+    }
+
+    #[test]
+    fn get_reads_single_line_with_origin() {
+        let mut store = LineStore::new();
+        store.push_chunk(file_chunk("first\nsecond\nthird"));
+
+        let line = store.get(2).unwrap();
+        assert_eq!("third", line.text());
+        assert_eq!(3, line.origin.line_number);
+        assert_eq!("app.log", &*line.origin.path);
+    }
+
+    #[test]
+    fn crlf_line_endings_are_stripped() {
+        let mut store = LineStore::new();
+        store.push_chunk(file_chunk("first\r\nsecond\r\n"));
+
+        assert_eq!("first", store.get(0).unwrap().text());
+        assert_eq!(vec!["first", "second"], texts(store.iter()));
+    }
+
+    #[test]
+    fn temp_files_are_private_and_unique() {
+        let (first, _) = TempFile::create("test").unwrap();
+        let (second, _) = TempFile::create("test").unwrap();
+        assert_ne!(first.path, second.path);
+        let mode = fs::metadata(&first.path).unwrap().permissions().mode();
+        assert_eq!(0o600, mode & 0o777);
+    }
+
+    #[test]
+    fn temp_files_are_known_until_dropped() {
+        let (temp, _) = TempFile::create("test").unwrap();
+        let path = temp.path.clone();
+        assert!(TEMP_FILES.lock().unwrap().contains(&path));
+        drop(temp);
+        assert!(!TEMP_FILES.lock().unwrap().contains(&path));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn missing_file_is_a_read_error_with_its_path() {
+        let err = LineStore::from_source(&Source::File("missing.log"))
+            .err()
+            .unwrap();
+        assert!(err.to_string().starts_with("missing.log: "));
+    }
+
+    #[test]
+    fn pipes_are_spilled_before_indexing() {
+        let (reader, mut writer) = io::pipe().unwrap();
+        writer.write_all(b"a\nb\n").unwrap();
+        drop(writer);
+        let path = format!("/dev/fd/{}", reader.as_raw_fd());
+
+        let store = LineStore::from_source(&Source::File(&path)).unwrap();
+        assert_eq!(vec!["a", "b"], texts(store.iter()));
+        assert_eq!(path, &*store.get(1).unwrap().origin.path);
+    }
+
+    #[test]
+    fn lines_span_files_and_memory() {
+        let mut store = LineStore::new();
+        store.push_chunk(file_chunk("a\nb\n"));
+        store.push_chunk(file_chunk(""));
+        store.push_chunk(file_chunk("c\n"));
+        store.push_lines(into_lines("live.log", "d\ne"));
+
+        assert_eq!(5, store.len());
+        assert_eq!(vec!["a", "b", "c", "d", "e"], texts(store.iter()));
+        assert_eq!(vec!["b", "c", "d", "e"], texts(store.iter_from(1)));
+        assert_eq!(vec!["c", "d", "e"], texts(store.iter_from(2)));
+        assert_eq!("c", store.get(2).unwrap().text());
+        assert_eq!("e", store.get(4).unwrap().text());
+        assert!(texts(store.iter_from(5)).is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    io::{self, Stdout, Write},
+    panic, process,
+    sync::{Mutex, Once, TryLockError},
+};
+
+use termion::{
+    cursor,
+    input::MouseTerminal,
+    raw::{IntoRawMode, RawTerminal},
+    screen::{AlternateScreen, ToMainScreen},
+};
+use tui::{backend::TermionBackend, Terminal};
+
+use crate::error::EXIT_ERROR;
+use crate::store::remove_temp_files;
+
+pub type Backend = TermionBackend<AlternateScreen<MouseTerminal<Stdout>>>;
+
+/// Raw mode of the terminal, kept here rather than in the backend so that the panic
+/// hook can leave it
+static RAW_MODE: Mutex<Option<RawTerminal<Stdout>>> = Mutex::new(None);
+
+/// Same as the private sequence that `MouseTerminal` writes when dropped
+const EXIT_MOUSE_SEQUENCE: &str = "\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l";
+
+/// Switches the terminal to raw mode and the alternate screen for the ui. A panic on
+/// any thread restores the terminal before its message is shown, and ends ire once
+/// the temp files are removed
+pub fn enter() -> io::Result<Terminal<Backend>> {
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let default = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore();
+            default(info);
+            remove_temp_files();
+            process::exit(EXIT_ERROR);
+        }));
+    });
+    *RAW_MODE.lock().unwrap() = Some(io::stdout().into_raw_mode()?);
+    let stdout = AlternateScreen::from(MouseTerminal::from(io::stdout()));
+    Terminal::new(TermionBackend::new(stdout)).inspect_err(|_| leave())
+}
+
+/// Leaves raw mode once the terminal from `enter` is dropped, which leaves the
+/// alternate screen
+pub fn leave() {
+    // the sequences leaving the alternate screen may still be buffered
+    let _ = io::stdout().flush();
+    if let Ok(mut raw_mode) = RAW_MODE.lock() {
+        raw_mode.take();
+    }
+}
+
+/// Leaves the ui without the `Terminal`, which may be in use on another thread. Does
+/// nothing when the ui was already left, or never entered
+fn restore() {
+    let raw_mode = match RAW_MODE.try_lock() {
+        Ok(mut raw_mode) => raw_mode.take(),
+        Err(TryLockError::Poisoned(raw_mode)) => raw_mode.into_inner().take(),
+        // the lock is held by the thread that panicked
+        Err(TryLockError::WouldBlock) => None,
+    };
+    if let Some(raw_mode) = raw_mode {
+        let mut stdout = io::stdout();
+        let _ = write!(
+            stdout,
+            "{}{}{}",
+            EXIT_MOUSE_SEQUENCE,
+            ToMainScreen,
+            cursor::Show
+        );
+        let _ = stdout.flush();
+        // leaves raw mode
+        drop(raw_mode);
+    }
+}